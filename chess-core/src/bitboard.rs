@@ -0,0 +1,657 @@
+use std::sync::OnceLock;
+
+use crate::gamestate::GameState;
+use crate::util::*;
+
+pub type Bitboard = u64;
+
+const KING: usize = 0;
+const QUEEN: usize = 1;
+const ROOK: usize = 2;
+const BISHOP: usize = 3;
+const KNIGHT: usize = 4;
+const PAWN: usize = 5;
+
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+const KNIGHT_OFFSETS: [(i32, i32); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_OFFSETS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+const ROOK_MAGICS: [u64; 64] = [
+    0x1080004008801020,
+    0x0840092002c03000,
+    0x1900200010400900,
+    0x0880100008000480,
+    0x4200100420080200,
+    0x8100020100080400,
+    0x0200040110886200,
+    0x0200008040220411,
+    0x0404800084400220,
+    0x0000401000402000,
+    0x0086001081220440,
+    0x0408800800100280,
+    0x000a001201040820,
+    0x8848800200840080,
+    0x4001000100040200,
+    0x0442000102105084,
+    0x9080010020804100,
+    0x0040404000201009,
+    0x0000808010002009,
+    0x2200090021d00100,
+    0x0008008008040080,
+    0x0004004002010040,
+    0x0011040008015042,
+    0x00000a0001768104,
+    0x0000800080204009,
+    0x2010004140002001,
+    0x9800200280100080,
+    0x1000100080080080,
+    0x0442000a00049020,
+    0x2100040080020080,
+    0x0800120400900148,
+    0x0010040a00128541,
+    0x2800804000800030,
+    0x1010002000400041,
+    0x4000200011004100,
+    0x0610008410800800,
+    0x0400802402800800,
+    0xc100020080800400,
+    0x0002000802000401,
+    0x0182085882000401,
+    0x0220204000808000,
+    0x2860100040024022,
+    0x0001002004110040,
+    0x99101042000a0020,
+    0x0004080004008080,
+    0x0010040002008080,
+    0x2012004881020004,
+    0x8300842444820011,
+    0x0088403882010200,
+    0x0820400080210100,
+    0x0110910040a00300,
+    0x0801100280080480,
+    0x0242009008200600,
+    0x1002000489500200,
+    0x0040800200010080,
+    0x0091800041000080,
+    0x0000209300488001,
+    0x04c1002414824001,
+    0x020020000b001041,
+    0x7000100004200901,
+    0x8002002004100802,
+    0x30010002084c0007,
+    0x0888221800813004,
+    0x4000002840840112,
+];
+const BISHOP_MAGICS: [u64; 64] = [
+    0xa010041108003100,
+    0x006082020a002900,
+    0x6810010619200000,
+    0x08281a0520000408,
+    0x0001104001000400,
+    0x0018901008048400,
+    0x00040a0210245280,
+    0x000200210808a402,
+    0x9140048410821200,
+    0x0800091010820041,
+    0x20504804832202c0,
+    0x0100091401081000,
+    0x8021011140000012,
+    0x0810020804450400,
+    0x208b0542109008a2,
+    0x0080084a08040204,
+    0x0040e2a80811244c,
+    0x2505022008008108,
+    0x0430220100420040,
+    0x010a040420220040,
+    0x1105000290400000,
+    0x0093001200822120,
+    0x4000a62048043004,
+    0x280120048a015004,
+    0x006090002a020814,
+    0x44042000240800d0,
+    0x01102800040a4400,
+    0x1004080080220040,
+    0x0001001011004024,
+    0x0010044000805040,
+    0x0914041200820100,
+    0x0004821012821480,
+    0x0024040500c05021,
+    0x0088611002080200,
+    0x0116080a00040020,
+    0x4000020080080080,
+    0x2450450140840040,
+    0x0000880201484100,
+    0x0222020404020092,
+    0x8081110600002e00,
+    0x2842101105000801,
+    0x1100809008001025,
+    0x00020202221c0400,
+    0x0422014022009020,
+    0x0210046102100c00,
+    0xc004008082029102,
+    0x00aa461801101200,
+    0x0404080080201108,
+    0x020542108c205002,
+    0x0410544804100100,
+    0x0040910841100000,
+    0x0400200042021100,
+    0x00004204850400c0,
+    0x0200100410a42102,
+    0x1040020801210102,
+    0x0805040410420000,
+    0x2884804130100200,
+    0x800c262201242000,
+    0x1058000194108800,
+    0x0014221054420204,
+    0x0104000012a02200,
+    0x0200881003300100,
+    0x0140400202840100,
+    0x0402020801010201,
+];
+
+#[derive(Clone, Copy, Default)]
+struct Magic {
+    mask: Bitboard,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupied: Bitboard) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct Tables {
+    knight: [Bitboard; 64],
+    king: [Bitboard; 64],
+    pawn: [[Bitboard; 64]; 2],
+    rook: [Magic; 64],
+    bishop: [Magic; 64],
+    sliding: Vec<Bitboard>,
+    between: Vec<Bitboard>,
+    line: Vec<Bitboard>,
+}
+
+impl Tables {
+    fn new() -> Self {
+        let leaper = |square: usize, offsets: &[(i32, i32)]| {
+            offsets
+                .iter()
+                .filter_map(|offset| offset_square(square, *offset))
+                .fold(0, |attacks, square| attacks | bit(square))
+        };
+
+        let mut knight = [0; 64];
+        let mut king = [0; 64];
+        let mut pawn = [[0; 64]; 2];
+        for square in 0..64 {
+            knight[square] = leaper(square, &KNIGHT_OFFSETS);
+            king[square] = leaper(square, &KING_OFFSETS);
+            pawn[color_index(Color::White)][square] = leaper(square, &[(1, -1), (1, 1)]);
+            pawn[color_index(Color::Black)][square] = leaper(square, &[(-1, -1), (-1, 1)]);
+        }
+
+        let mut sliding = Vec::new();
+        let rook = magic_table(&ROOK_MAGICS, &ROOK_DIRECTIONS, &mut sliding);
+        let bishop = magic_table(&BISHOP_MAGICS, &BISHOP_DIRECTIONS, &mut sliding);
+
+        let mut between = vec![0; 64 * 64];
+        let mut line = vec![0; 64 * 64];
+        for src in 0..64 {
+            for direction @ (rank, file) in ROOK_DIRECTIONS.into_iter().chain(BISHOP_DIRECTIONS) {
+                let full_line = bit(src) | ray_attacks(src, 0, &[direction, (-rank, -file)]);
+                let mut squares = 0;
+                let mut current = src;
+                while let Some(dst) = offset_square(current, direction) {
+                    between[src * 64 + dst] = squares;
+                    line[src * 64 + dst] = full_line;
+                    squares |= bit(dst);
+                    current = dst;
+                }
+            }
+        }
+
+        Self {
+            knight,
+            king,
+            pawn,
+            rook,
+            bishop,
+            sliding,
+            between,
+            line,
+        }
+    }
+
+    fn between(&self, src: usize, dst: usize) -> Bitboard {
+        self.between[src * 64 + dst]
+    }
+
+    fn line(&self, src: usize, dst: usize) -> Bitboard {
+        self.line[src * 64 + dst]
+    }
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(Tables::new)
+}
+
+fn magic_table(
+    magics: &[u64; 64],
+    directions: &[(i32, i32)],
+    sliding: &mut Vec<Bitboard>,
+) -> [Magic; 64] {
+    let mut table = [Magic::default(); 64];
+    for (square, entry) in table.iter_mut().enumerate() {
+        let mask = relevant_occupancy(square, directions);
+        let bits = mask.count_ones();
+        *entry = Magic {
+            mask,
+            magic: magics[square],
+            shift: 64 - bits,
+            offset: sliding.len(),
+        };
+        sliding.resize(sliding.len() + (1 << bits), 0);
+
+        let mut subset: Bitboard = 0;
+        loop {
+            sliding[entry.index(subset)] = ray_attacks(square, subset, directions);
+            subset = subset.wrapping_sub(mask) & mask;
+            if subset == 0 {
+                break;
+            }
+        }
+    }
+    table
+}
+
+fn offset_square(square: usize, (rank, file): (i32, i32)) -> Option<usize> {
+    let (rank, file) = ((square / 8) as i32 + rank, (square % 8) as i32 + file);
+    ((0..8).contains(&rank) && (0..8).contains(&file)).then_some((rank * 8 + file) as usize)
+}
+
+fn ray_attacks(square: usize, occupied: Bitboard, directions: &[(i32, i32)]) -> Bitboard {
+    let mut attacks = 0;
+    for direction in directions {
+        let mut current = square;
+        while let Some(next) = offset_square(current, *direction) {
+            attacks |= bit(next);
+            if occupied & bit(next) != 0 {
+                break;
+            }
+            current = next;
+        }
+    }
+    attacks
+}
+
+fn relevant_occupancy(square: usize, directions: &[(i32, i32)]) -> Bitboard {
+    let mut mask = 0;
+    for direction in directions {
+        let mut current = square;
+        while let Some(next) = offset_square(current, *direction) {
+            if offset_square(next, *direction).is_none() {
+                break;
+            }
+            mask |= bit(next);
+            current = next;
+        }
+    }
+    mask
+}
+
+fn bit(square: usize) -> Bitboard {
+    1 << square
+}
+
+fn squares(mut bitboard: Bitboard) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if bitboard == 0 {
+            return None;
+        }
+        let square = bitboard.trailing_zeros() as usize;
+        bitboard &= bitboard - 1;
+        Some(square)
+    })
+}
+
+fn color_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+fn piece_index(state: PieceState) -> usize {
+    match state {
+        PieceState::King => KING,
+        PieceState::Queen => QUEEN,
+        PieceState::Rook => ROOK,
+        PieceState::Bishop => BISHOP,
+        PieceState::Knight => KNIGHT,
+        PieceState::Pawn => PAWN,
+    }
+}
+
+pub fn knight_attacks(square: usize) -> Bitboard {
+    tables().knight[square]
+}
+
+pub fn rook_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.sliding[tables.rook[square].index(occupied)]
+}
+
+pub fn bishop_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.sliding[tables.bishop[square].index(occupied)]
+}
+
+pub fn point_to_index((x, y): Point) -> usize {
+    ((7 - x) * 8 + y) as usize
+}
+
+pub fn index_to_point(square: usize) -> Point {
+    (7 - (square / 8) as i32, (square % 8) as i32)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pieces: [[Bitboard; 6]; 2],
+    colors: [Bitboard; 2],
+    side: Color,
+    castling: [[bool; 2]; 2],
+    en_passant: Option<usize>,
+}
+
+impl Position {
+    pub fn new(gamestate: &GameState) -> Self {
+        let mut position = Self {
+            pieces: [[0; 6]; 2],
+            colors: [0; 2],
+            side: gamestate.players_turn(),
+            castling: [[false; 2]; 2],
+            en_passant: gamestate.en_passant().map(point_to_index),
+        };
+        for color in [Color::White, Color::Black] {
+            let index = color_index(color);
+            for point in gamestate.pieces(color) {
+                let piece = gamestate
+                    .get_piece(*point)
+                    .expect("piece sets match the board");
+                position.pieces[index][piece_index(piece.state)] |= bit(point_to_index(*point));
+                position.colors[index] |= bit(point_to_index(*point));
+            }
+            position.castling[index] = [
+                gamestate.can_castle(color, true),
+                gamestate.can_castle(color, false),
+            ];
+        }
+        position
+    }
+
+    fn occupied(&self) -> Bitboard {
+        self.colors[0] | self.colors[1]
+    }
+
+    fn king_square(&self, color: Color) -> usize {
+        self.pieces[color_index(color)][KING].trailing_zeros() as usize
+    }
+
+    fn attackers(&self, square: usize, by: Color, occupied: Bitboard) -> Bitboard {
+        let tables = tables();
+        let index = color_index(by);
+        let pieces = &self.pieces[index];
+        (tables.pawn[1 - index][square] & pieces[PAWN])
+            | (tables.knight[square] & pieces[KNIGHT])
+            | (tables.king[square] & pieces[KING])
+            | (bishop_attacks(square, occupied) & (pieces[BISHOP] | pieces[QUEEN]))
+            | (rook_attacks(square, occupied) & (pieces[ROOK] | pieces[QUEEN]))
+    }
+
+    pub fn is_attacked(&self, square: usize, by: Color) -> bool {
+        self.attackers(square, by, self.occupied()) != 0
+    }
+
+    pub fn in_check(&self) -> bool {
+        self.is_attacked(self.king_square(self.side), self.side.opposite())
+    }
+
+    /// Whether the side that just moved left its own king attacked, which
+    /// no legal move can do.
+    pub fn opponent_in_check(&self) -> bool {
+        self.is_attacked(self.king_square(self.side.opposite()), self.side)
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let tables = tables();
+        let us = color_index(self.side);
+        let opponent = self.side.opposite();
+        let ours = self.colors[us];
+        let theirs = self.colors[1 - us];
+        let their_pieces = &self.pieces[1 - us];
+        let occupied = ours | theirs;
+        let king = self.king_square(self.side);
+        let mut moves = Vec::with_capacity(64);
+
+        let without_king = occupied & !bit(king);
+        for dst in squares(tables.king[king] & !ours) {
+            if self.attackers(dst, opponent, without_king) == 0 {
+                push_move(&mut moves, king, dst);
+            }
+        }
+
+        let checkers = self.attackers(king, opponent, occupied);
+        if checkers.count_ones() > 1 {
+            return moves;
+        }
+        let check_mask = match squares(checkers).next() {
+            Some(checker) => tables.between(king, checker) | checkers,
+            None => !0,
+        };
+
+        let mut pin_masks = [!0; 64];
+        let snipers = (rook_attacks(king, theirs) & (their_pieces[ROOK] | their_pieces[QUEEN]))
+            | (bishop_attacks(king, theirs) & (their_pieces[BISHOP] | their_pieces[QUEEN]));
+        for sniper in squares(snipers) {
+            let blockers = tables.between(king, sniper) & occupied;
+            if blockers.count_ones() == 1 && blockers & ours != 0 {
+                pin_masks[blockers.trailing_zeros() as usize] = tables.line(king, sniper);
+            }
+        }
+
+        for piece in [QUEEN, ROOK, BISHOP, KNIGHT] {
+            for src in squares(self.pieces[us][piece]) {
+                let attacks = match piece {
+                    QUEEN => rook_attacks(src, occupied) | bishop_attacks(src, occupied),
+                    ROOK => rook_attacks(src, occupied),
+                    BISHOP => bishop_attacks(src, occupied),
+                    _ => tables.knight[src],
+                };
+                for dst in squares(attacks & !ours & check_mask & pin_masks[src]) {
+                    push_move(&mut moves, src, dst);
+                }
+            }
+        }
+
+        let (forward, start_rank): (i32, usize) = if self.side == Color::White {
+            (8, 1)
+        } else {
+            (-8, 6)
+        };
+        for src in squares(self.pieces[us][PAWN]) {
+            let allowed = check_mask & pin_masks[src];
+            let single = (src as i32 + forward) as usize;
+            if occupied & bit(single) == 0 {
+                if allowed & bit(single) != 0 {
+                    push_pawn_move(&mut moves, src, single);
+                }
+                let double = (single as i32 + forward) as usize;
+                if src / 8 == start_rank
+                    && occupied & bit(double) == 0
+                    && allowed & bit(double) != 0
+                {
+                    push_move(&mut moves, src, double);
+                }
+            }
+
+            for dst in squares(tables.pawn[us][src] & theirs & allowed) {
+                push_pawn_move(&mut moves, src, dst);
+            }
+
+            if let Some(en_passant) = self.en_passant {
+                if tables.pawn[us][src] & bit(en_passant) != 0 {
+                    let captured = (en_passant as i32 - forward) as usize;
+                    let after = (occupied ^ bit(src) ^ bit(captured)) | bit(en_passant);
+                    if self.attackers(king, opponent, after) & !bit(captured) == 0 {
+                        push_move(&mut moves, src, en_passant);
+                    }
+                }
+            }
+        }
+
+        // Castling rights only exist with the king on the e-file and the rook
+        // in its corner: `GameState::can_castle` and the FEN reader check both,
+        // so the rook squares here are fixed offsets from the king.
+        if checkers == 0 {
+            for (side, step) in [(0, 1), (1, -1)] {
+                if !self.castling[us][side] {
+                    continue;
+                }
+                debug_assert_eq!(king % 8, 4, "castling rights with the king off the e-file");
+                let rook = if step > 0 { king + 3 } else { king - 4 };
+                let path_clear = tables.between(king, rook) & occupied == 0;
+                let transit = [
+                    (king as i32 + step) as usize,
+                    (king as i32 + 2 * step) as usize,
+                ];
+                if path_clear
+                    && transit
+                        .iter()
+                        .all(|square| self.attackers(*square, opponent, occupied) == 0)
+                {
+                    push_move(&mut moves, king, transit[1]);
+                }
+            }
+        }
+
+        moves
+    }
+}
+
+fn push_move(moves: &mut Vec<Move>, src: usize, dst: usize) {
+    moves.push(Move::new(index_to_point(src), index_to_point(dst)));
+}
+
+fn push_pawn_move(moves: &mut Vec<Move>, src: usize, dst: usize) {
+    if dst / 8 == 0 || dst / 8 == 7 {
+        moves.extend(
+            PROMOTION_PIECES.iter().map(|state| {
+                Move::with_promotion(index_to_point(src), index_to_point(dst), *state)
+            }),
+        );
+    } else {
+        push_move(moves, src, dst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves_from(fen: &str, square: &str) -> Vec<String> {
+        let gamestate = GameState::from_fen(fen).expect("valid FEN");
+        let mut moves: Vec<String> = Position::new(&gamestate)
+            .legal_moves()
+            .iter()
+            .map(Move::to_string)
+            .filter(|uci| uci.starts_with(square))
+            .collect();
+        moves.sort();
+        moves
+    }
+
+    fn all_moves(fen: &str) -> Vec<String> {
+        moves_from(fen, "")
+    }
+
+    #[test]
+    fn pinned_pieces_stay_on_the_pin_line() {
+        assert_eq!(
+            moves_from("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1", "e2"),
+            ["e2e3", "e2e4", "e2e5", "e2e6", "e2e7"]
+        );
+        assert_eq!(
+            moves_from("4k3/8/8/b7/8/8/3B4/4K3 w - - 0 1", "d2"),
+            ["d2a5", "d2b4", "d2c3"]
+        );
+        assert!(moves_from("4k3/8/8/b7/8/8/3N4/4K3 w - - 0 1", "d2").is_empty());
+        assert!(moves_from("4k3/8/8/8/7b/8/5P2/4K3 w - - 0 1", "f2").is_empty());
+    }
+
+    #[test]
+    fn check_is_answered_by_moving_blocking_or_capturing() {
+        assert_eq!(
+            all_moves("4k3/4r3/8/8/8/8/3N4/4K3 w - - 0 1"),
+            ["d2e4", "e1d1", "e1f1", "e1f2"]
+        );
+        assert_eq!(
+            all_moves("4k3/8/8/8/8/8/3q4/3RK3 w - - 0 1"),
+            ["d1d2", "e1d2", "e1f1"]
+        );
+    }
+
+    #[test]
+    fn double_check_only_allows_king_moves() {
+        assert_eq!(
+            all_moves("4k3/8/8/8/8/3n4/2B5/4K2r w - - 0 1"),
+            ["e1d2", "e1e2"]
+        );
+    }
+
+    #[test]
+    fn en_passant_cannot_expose_the_king() {
+        assert_eq!(
+            moves_from("8/8/8/K2pP2r/8/8/8/4k3 w - d6 0 1", "e5"),
+            ["e5e6"]
+        );
+        assert_eq!(
+            moves_from("8/8/8/K2pP3/8/8/8/4k3 w - d6 0 1", "e5"),
+            ["e5d6", "e5e6"]
+        );
+        assert_eq!(
+            moves_from("8/8/8/3pP3/8/8/8/K3k3 w - d6 0 1", "e5"),
+            ["e5d6", "e5e6"]
+        );
+    }
+
+    #[test]
+    fn en_passant_can_remove_a_checking_pawn() {
+        assert_eq!(
+            moves_from("8/8/8/3pP3/4K3/8/8/4k3 w - d6 0 1", "e5"),
+            ["e5d6"]
+        );
+    }
+}
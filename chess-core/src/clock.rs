@@ -0,0 +1,338 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::util::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stage {
+    pub moves: Option<u32>,
+    pub time: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bonus {
+    None,
+    Increment(Duration),
+    Delay(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeControl {
+    pub stages: Vec<Stage>,
+    pub bonus: Bonus,
+}
+
+impl TimeControl {
+    pub fn sudden_death(time: Duration) -> Self {
+        Self {
+            stages: vec![Stage { moves: None, time }],
+            bonus: Bonus::None,
+        }
+    }
+
+    pub fn fischer(time: Duration, increment: Duration) -> Self {
+        Self {
+            bonus: Bonus::Increment(increment),
+            ..Self::sudden_death(time)
+        }
+    }
+
+    pub fn bronstein(time: Duration, delay: Duration) -> Self {
+        Self {
+            bonus: Bonus::Delay(delay),
+            ..Self::sudden_death(time)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeControlError(String);
+
+impl fmt::Display for TimeControlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid time control \"{}\"", self.0)
+    }
+}
+
+impl Error for TimeControlError {}
+
+/// Parses controls such as `5`, `3+2`, `5d3` or `40/90:30+30`: stages in
+/// minutes separated by `:`, each optionally prefixed by a move count, then
+/// an increment (`+`) or Bronstein delay (`d`) in seconds. A final stage
+/// with a move count repeats.
+impl FromStr for TimeControl {
+    type Err = TimeControlError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let error = || TimeControlError(text.to_string());
+        let seconds = |value: &str| -> Result<Duration, TimeControlError> {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+                .map(Duration::from_secs_f64)
+                .ok_or_else(error)
+        };
+
+        let (stages, bonus) = if let Some((stages, increment)) = text.split_once('+') {
+            (stages, Bonus::Increment(seconds(increment)?))
+        } else if let Some((stages, delay)) = text.split_once('d') {
+            (stages, Bonus::Delay(seconds(delay)?))
+        } else {
+            (text, Bonus::None)
+        };
+
+        let stages = stages
+            .split(':')
+            .map(|stage| {
+                let (moves, minutes) = match stage.split_once('/') {
+                    Some((moves, minutes)) => {
+                        let moves = moves.parse().ok().filter(|moves| *moves > 0);
+                        (Some(moves.ok_or_else(error)?), minutes)
+                    }
+                    None => (None, stage),
+                };
+                let time = seconds(minutes)? * 60;
+                Ok(Stage { moves, time })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let unlimited = stages.iter().position(|stage| stage.moves.is_none());
+        if unlimited.is_some_and(|index| index != stages.len() - 1) {
+            return Err(error());
+        }
+        Ok(Self { stages, bonus })
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, stage) in self.stages.iter().enumerate() {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            if let Some(moves) = stage.moves {
+                write!(f, "{moves}/")?;
+            }
+            write!(f, "{}", stage.time.as_secs_f64() / 60.0)?;
+        }
+        match self.bonus {
+            Bonus::None => Ok(()),
+            Bonus::Increment(increment) => write!(f, "+{}", increment.as_secs_f64()),
+            Bonus::Delay(delay) => write!(f, "d{}", delay.as_secs_f64()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Clock {
+    control: TimeControl,
+    remaining: [Duration; 2],
+    stage: [usize; 2],
+    stage_moves: [u32; 2],
+    running: Option<(Color, Instant)>,
+    flagged: Option<Color>,
+}
+
+fn side(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        let time = control
+            .stages
+            .first()
+            .map_or(Duration::ZERO, |stage| stage.time);
+        Self {
+            control,
+            remaining: [time; 2],
+            stage: [0; 2],
+            stage_moves: [0; 2],
+            running: None,
+            flagged: None,
+        }
+    }
+
+    pub fn control(&self) -> &TimeControl {
+        &self.control
+    }
+
+    pub fn running(&self) -> Option<Color> {
+        self.running.map(|(color, _)| color)
+    }
+
+    pub fn remaining(&self, color: Color, now: Instant) -> Duration {
+        let remaining = self.remaining[side(color)];
+        match self.running {
+            Some((running, since)) if running == color => {
+                remaining.saturating_sub(now.saturating_duration_since(since))
+            }
+            _ => remaining,
+        }
+    }
+
+    pub fn flagged(&self, now: Instant) -> Option<Color> {
+        self.flagged.or_else(|| {
+            self.running()
+                .filter(|&color| self.remaining(color, now).is_zero())
+        })
+    }
+
+    /// Ends `mover`'s turn: charges the time used, applies the bonus and any
+    /// stage time earned, then starts the opponent's clock.
+    pub fn press(&mut self, mover: Color, now: Instant) {
+        if self.flagged(now).is_some() {
+            self.stop(now);
+            return;
+        }
+        let used = match self.running {
+            Some((running, since)) if running == mover => now.saturating_duration_since(since),
+            _ => Duration::ZERO,
+        };
+        let index = side(mover);
+        self.remaining[index] = self.remaining[index].saturating_sub(used);
+        self.remaining[index] += match self.control.bonus {
+            Bonus::None => Duration::ZERO,
+            Bonus::Increment(increment) => increment,
+            Bonus::Delay(delay) => used.min(delay),
+        };
+
+        self.stage_moves[index] += 1;
+        let stages = &self.control.stages;
+        if let Some(stage) = stages.get(self.stage[index]) {
+            if stage.moves == Some(self.stage_moves[index]) {
+                self.stage[index] = (self.stage[index] + 1).min(stages.len() - 1);
+                self.stage_moves[index] = 0;
+                self.remaining[index] += stages[self.stage[index]].time;
+            }
+        }
+        self.running = Some((mover.opposite(), now));
+    }
+
+    /// Hands the move to `color` without applying any bonus, as when a move
+    /// is taken back.
+    pub fn switch_to(&mut self, color: Color, now: Instant) {
+        if self.running.is_none() || self.flagged.is_some() {
+            return;
+        }
+        self.stop(now);
+        self.running = Some((color, now));
+    }
+
+    pub fn stop(&mut self, now: Instant) {
+        if let Some((color, since)) = self.running.take() {
+            let index = side(color);
+            self.remaining[index] =
+                self.remaining[index].saturating_sub(now.saturating_duration_since(since));
+            if self.remaining[index].is_zero() {
+                self.flagged = Some(color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn parses_time_controls() {
+        assert_eq!("5".parse(), Ok(TimeControl::sudden_death(secs(300))));
+        assert_eq!("3+2".parse(), Ok(TimeControl::fischer(secs(180), secs(2))));
+        assert_eq!(
+            "5d3".parse(),
+            Ok(TimeControl::bronstein(secs(300), secs(3)))
+        );
+        assert_eq!(
+            "40/90:30+30".parse(),
+            Ok(TimeControl {
+                stages: vec![
+                    Stage {
+                        moves: Some(40),
+                        time: secs(90 * 60)
+                    },
+                    Stage {
+                        moves: None,
+                        time: secs(30 * 60)
+                    },
+                ],
+                bonus: Bonus::Increment(secs(30)),
+            })
+        );
+        assert_eq!(
+            "40/90:30+30".parse::<TimeControl>().unwrap().to_string(),
+            "40/90:30+30"
+        );
+        for invalid in ["", "x", "3+", "0/5", "5:40/90", "-1"] {
+            assert!(invalid.parse::<TimeControl>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn fischer_increment_is_added_after_each_move() {
+        let start = Instant::now();
+        let mut clock = Clock::new(TimeControl::fischer(secs(60), secs(2)));
+        clock.press(Color::White, start);
+        assert_eq!(clock.remaining(Color::White, start), secs(62));
+        assert_eq!(clock.running(), Some(Color::Black));
+
+        let later = start + secs(10);
+        assert_eq!(clock.remaining(Color::Black, later), secs(50));
+        clock.press(Color::Black, later);
+        assert_eq!(clock.remaining(Color::Black, later), secs(52));
+        assert_eq!(clock.remaining(Color::White, later + secs(5)), secs(57));
+    }
+
+    #[test]
+    fn bronstein_delay_refunds_at_most_the_delay() {
+        let start = Instant::now();
+        let mut clock = Clock::new(TimeControl::bronstein(secs(60), secs(3)));
+        clock.press(Color::White, start);
+        clock.press(Color::Black, start + secs(2));
+        assert_eq!(clock.remaining(Color::Black, start + secs(2)), secs(60));
+        clock.press(Color::White, start + secs(12));
+        assert_eq!(clock.remaining(Color::White, start + secs(12)), secs(53));
+    }
+
+    #[test]
+    fn later_stages_add_time_after_their_move_count() {
+        let start = Instant::now();
+        let mut clock = Clock::new("2/1:1/1:5".parse().unwrap());
+        let mut now = start;
+        for _ in 0..4 {
+            now += secs(1);
+            clock.press(clock.running().unwrap_or(Color::White), now);
+        }
+        assert_eq!(clock.remaining(Color::White, now), secs(59 + 60));
+        now += secs(1);
+        clock.press(Color::White, now);
+        assert_eq!(clock.remaining(Color::White, now), secs(58 + 60 + 300));
+    }
+
+    #[test]
+    fn running_out_of_time_flags() {
+        let start = Instant::now();
+        let mut clock = Clock::new(TimeControl::sudden_death(secs(1)));
+        assert_eq!(clock.flagged(start + secs(5)), None);
+        clock.press(Color::White, start);
+        assert_eq!(clock.flagged(start + secs(1)), Some(Color::Black));
+
+        clock.press(Color::Black, start + secs(2));
+        assert_eq!(clock.running(), None);
+        assert_eq!(clock.flagged(start + secs(3)), Some(Color::Black));
+        assert_eq!(
+            clock.remaining(Color::Black, start + secs(3)),
+            Duration::ZERO
+        );
+    }
+}
@@ -0,0 +1,515 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::eval::{self, piece_value};
+use crate::gamestate::GameState;
+use crate::util::*;
+use crate::BOARD_LENGTH;
+
+const INFINITY: i32 = 32_000;
+const MATE: i32 = 31_000;
+const MAX_PLY: usize = 128;
+const TABLE_SIZE: usize = 1 << 18;
+const CHECK_INTERVAL: u64 = 2048;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub movetime: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    Centipawns(i32),
+    Mate(i32),
+}
+
+impl Score {
+    fn from_search(score: i32) -> Self {
+        if score.abs() >= MATE - MAX_PLY as i32 {
+            let moves = (MATE - score.abs() + 1) / 2;
+            Score::Mate(if score > 0 { moves } else { -moves })
+        } else {
+            Score::Centipawns(score)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchInfo {
+    pub depth: u32,
+    pub score: Score,
+    pub nodes: u64,
+    pub time: Duration,
+    pub pv: Vec<Move>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    key: u64,
+    depth: i32,
+    score: i32,
+    bound: Bound,
+    best: Option<Move>,
+}
+
+pub struct Engine {
+    table: Vec<Option<Entry>>,
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    history: [[i32; 64]; 64],
+    path: Vec<u64>,
+    root_best: Option<Move>,
+    nodes: u64,
+    deadline: Option<Instant>,
+    stop: Arc<AtomicBool>,
+    aborted: bool,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self {
+            table: vec![None; TABLE_SIZE],
+            killers: [[None; 2]; MAX_PLY],
+            history: [[0; 64]; 64],
+            path: Vec::new(),
+            root_best: None,
+            nodes: 0,
+            deadline: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            aborted: false,
+        }
+    }
+
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    pub fn clear(&mut self) {
+        self.table.fill(None);
+        self.killers = [[None; 2]; MAX_PLY];
+        self.history = [[0; 64]; 64];
+    }
+
+    pub fn search(
+        &mut self,
+        gamestate: &GameState,
+        limits: SearchLimits,
+        mut report: impl FnMut(&SearchInfo),
+    ) -> Option<Move> {
+        let start = Instant::now();
+        let mut gamestate = gamestate.clone();
+        self.deadline = limits.movetime.map(|movetime| start + movetime);
+        self.nodes = 0;
+        self.aborted = false;
+        self.killers = [[None; 2]; MAX_PLY];
+        for row in self.history.iter_mut() {
+            for score in row.iter_mut() {
+                *score /= 8;
+            }
+        }
+        self.path = gamestate.position_hashes().to_vec();
+
+        let mut best = gamestate.legal_moves().first().copied()?;
+        let max_depth = limits.depth.unwrap_or(MAX_PLY as u32 / 2).max(1);
+        for depth in 1..=max_depth {
+            self.root_best = None;
+            let score = self.alpha_beta(&mut gamestate, depth as i32, 0, -INFINITY, INFINITY);
+            if self.aborted {
+                break;
+            }
+            best = self.root_best.unwrap_or(best);
+            report(&SearchInfo {
+                depth,
+                score: Score::from_search(score),
+                nodes: self.nodes,
+                time: start.elapsed(),
+                pv: self.principal_variation(&mut gamestate, depth),
+            });
+        }
+        Some(best)
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if !self.aborted && self.nodes.is_multiple_of(CHECK_INTERVAL) {
+            self.aborted = self.stop.load(Ordering::Relaxed)
+                || self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline);
+        }
+        self.aborted
+    }
+
+    fn alpha_beta(
+        &mut self,
+        gamestate: &mut GameState,
+        mut depth: i32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        if ply > 0 {
+            if self.should_stop() {
+                return 0;
+            }
+            if gamestate.halfmove_clock() >= 100 || self.is_repetition() {
+                return 0;
+            }
+        }
+        if ply >= MAX_PLY - 1 {
+            return evaluate(gamestate);
+        }
+
+        let in_check = gamestate.in_check();
+        if in_check {
+            depth += 1;
+        }
+        if depth <= 0 {
+            return self.quiescence(gamestate, ply, alpha, beta);
+        }
+        self.nodes += 1;
+
+        let key = gamestate.hash();
+        let mut table_move = None;
+        if let Some(entry) = self.probe(key) {
+            table_move = entry.best;
+            if ply > 0 && entry.depth >= depth {
+                let score = score_from_table(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        let mut moves = gamestate.legal_moves();
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        moves.sort_by_cached_key(|candidate| {
+            -self.move_score(gamestate, *candidate, table_move, ply)
+        });
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        for candidate in moves {
+            let quiet = captured_piece(gamestate, candidate).is_none();
+            let undo = gamestate.make_move(candidate);
+            self.path.push(gamestate.hash());
+            let score = -self.alpha_beta(gamestate, depth - 1, ply + 1, -beta, -alpha);
+            self.path.pop();
+            gamestate.unmake_move(undo);
+            if self.aborted {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(candidate);
+                if ply == 0 {
+                    self.root_best = Some(candidate);
+                }
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                if quiet {
+                    self.record_cutoff(candidate, depth, ply);
+                }
+                break;
+            }
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.store(Entry {
+            key,
+            depth,
+            score: score_to_table(best_score, ply),
+            bound,
+            best: best_move,
+        });
+        best_score
+    }
+
+    fn quiescence(
+        &mut self,
+        gamestate: &mut GameState,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        if self.should_stop() {
+            return 0;
+        }
+        self.nodes += 1;
+        if ply >= MAX_PLY - 1 {
+            return evaluate(gamestate);
+        }
+
+        // In check there is no standing pat: every evasion is searched, and
+        // having none is mate rather than a quiet position.
+        let in_check = gamestate.in_check();
+        if !in_check {
+            let stand_pat = evaluate(gamestate);
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+        }
+
+        let moves = gamestate.legal_moves();
+        if in_check && moves.is_empty() {
+            return -MATE + ply as i32;
+        }
+        let mut captures: Vec<Move> = moves
+            .into_iter()
+            .filter(|candidate| {
+                in_check
+                    || captured_piece(gamestate, *candidate).is_some()
+                    || candidate.promotion == Some(PieceState::Queen)
+            })
+            .collect();
+        captures.sort_by_cached_key(|candidate| -self.move_score(gamestate, *candidate, None, ply));
+
+        for capture in captures {
+            let undo = gamestate.make_move(capture);
+            let score = -self.quiescence(gamestate, ply + 1, -beta, -alpha);
+            gamestate.unmake_move(undo);
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+
+    fn move_score(
+        &self,
+        gamestate: &GameState,
+        candidate: Move,
+        table_move: Option<Move>,
+        ply: usize,
+    ) -> i32 {
+        if Some(candidate) == table_move {
+            return 1_000_000;
+        }
+        if let Some(victim) = captured_piece(gamestate, candidate) {
+            let attacker = gamestate
+                .get_piece(candidate.src)
+                .map_or(0, |piece| piece_value(piece.state));
+            return 100_000 + 10 * piece_value(victim) - attacker;
+        }
+        if candidate.promotion == Some(PieceState::Queen) {
+            return 90_000;
+        }
+        if self.killers[ply][0] == Some(candidate) {
+            return 80_000;
+        }
+        if self.killers[ply][1] == Some(candidate) {
+            return 79_000;
+        }
+        self.history[square_index(candidate.src)][square_index(candidate.dst)]
+    }
+
+    fn record_cutoff(&mut self, candidate: Move, depth: i32, ply: usize) {
+        if self.killers[ply][0] != Some(candidate) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(candidate);
+        }
+        let history = &mut self.history[square_index(candidate.src)][square_index(candidate.dst)];
+        *history = (*history + depth * depth).min(70_000);
+    }
+
+    fn is_repetition(&self) -> bool {
+        match self.path.split_last() {
+            Some((current, earlier)) => earlier.contains(current),
+            None => false,
+        }
+    }
+
+    fn probe(&self, key: u64) -> Option<Entry> {
+        self.table[key as usize % TABLE_SIZE].filter(|entry| entry.key == key)
+    }
+
+    fn store(&mut self, entry: Entry) {
+        let slot = &mut self.table[entry.key as usize % TABLE_SIZE];
+        if slot.is_none_or(|old| old.key != entry.key || old.depth <= entry.depth) {
+            *slot = Some(entry);
+        }
+    }
+
+    fn principal_variation(&self, gamestate: &mut GameState, depth: u32) -> Vec<Move> {
+        let mut pv = Vec::new();
+        let mut undos = Vec::new();
+        while pv.len() < depth as usize {
+            let Some(best) = self.probe(gamestate.hash()).and_then(|entry| entry.best) else {
+                break;
+            };
+            if !gamestate.legal_moves().contains(&best) {
+                break;
+            }
+            pv.push(best);
+            undos.push(gamestate.make_move(best));
+        }
+        while let Some(undo) = undos.pop() {
+            gamestate.unmake_move(undo);
+        }
+        pv
+    }
+}
+
+fn evaluate(gamestate: &GameState) -> i32 {
+    let score = eval::evaluate(gamestate);
+    if gamestate.players_turn() == Color::White {
+        score
+    } else {
+        -score
+    }
+}
+
+fn captured_piece(gamestate: &GameState, candidate: Move) -> Option<PieceState> {
+    match gamestate.get_piece(candidate.dst) {
+        Some(piece) => Some(piece.state),
+        None if gamestate.en_passant() == Some(candidate.dst)
+            && gamestate
+                .get_piece(candidate.src)
+                .is_some_and(|piece| piece.state == PieceState::Pawn) =>
+        {
+            Some(PieceState::Pawn)
+        }
+        None => None,
+    }
+}
+
+fn square_index((x, y): Point) -> usize {
+    (x * BOARD_LENGTH + y) as usize
+}
+
+fn score_to_table(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score + ply as i32
+    } else if score <= -MATE + MAX_PLY as i32 {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_table(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score - ply as i32
+    } else if score <= -MATE + MAX_PLY as i32 {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best_move(fen: &str, depth: u32) -> String {
+        let gamestate = GameState::from_fen(fen).expect("valid FEN");
+        let limits = SearchLimits {
+            depth: Some(depth),
+            ..SearchLimits::default()
+        };
+        Engine::new()
+            .search(&gamestate, limits, |_| {})
+            .expect("position has legal moves")
+            .to_string()
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        assert_eq!(best_move("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 2), "a1a8");
+    }
+
+    #[test]
+    fn wins_a_hanging_queen() {
+        assert_eq!(
+            best_move(
+                "rnb1kbnr/pppp1ppp/8/4p1q1/3P4/8/PPP1PPPP/RNBQKBNR w KQkq - 0 3",
+                3
+            ),
+            "c1g5"
+        );
+    }
+
+    #[test]
+    fn reports_mate_scores() {
+        let gamestate =
+            GameState::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").expect("valid FEN");
+        let mut last = None;
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        Engine::new().search(&gamestate, limits, |info| last = Some(info.clone()));
+        let info = last.expect("at least one iteration");
+        assert_eq!(info.score, Score::Mate(1));
+        assert_eq!(
+            info.pv.first().map(ToString::to_string).as_deref(),
+            Some("a1a8")
+        );
+    }
+
+    fn quiescence(fen: &str) -> i32 {
+        let mut gamestate = GameState::from_fen(fen).expect("valid FEN");
+        Engine::new().quiescence(&mut gamestate, 0, -INFINITY, INFINITY)
+    }
+
+    #[test]
+    fn quiescence_does_not_stand_pat_in_check() {
+        assert_eq!(quiescence("R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1"), -MATE);
+        assert!(quiescence("4k3/8/8/8/8/8/8/4RK2 b - - 0 1") > -MATE + MAX_PLY as i32);
+    }
+
+    #[test]
+    fn no_move_without_legal_moves() {
+        let gamestate =
+            GameState::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1").expect("valid FEN");
+        assert_eq!(
+            Engine::new().search(&gamestate, SearchLimits::default(), |_| {}),
+            None
+        );
+    }
+
+    #[test]
+    fn stops_at_the_movetime() {
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_millis(200)),
+            ..SearchLimits::default()
+        };
+        let start = Instant::now();
+        assert!(Engine::new()
+            .search(&GameState::new(), limits, |_| {})
+            .is_some());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
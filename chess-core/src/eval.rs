@@ -0,0 +1,476 @@
+use std::fmt;
+use std::ops::{Add, AddAssign, Mul};
+
+use crate::bitboard::{bishop_attacks, knight_attacks, point_to_index, rook_attacks, Bitboard};
+use crate::gamestate::GameState;
+use crate::util::*;
+use crate::BOARD_LENGTH;
+
+const MAX_PHASE: i32 = 24;
+const BISHOP_PAIR: i32 = 30;
+
+#[rustfmt::skip]
+const PAWN_MIDDLEGAME: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const PAWN_ENDGAME: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    80, 80, 80, 80, 80, 80, 80, 80,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    30, 30, 30, 30, 30, 30, 30, 30,
+    20, 20, 20, 20, 20, 20, 20, 20,
+    10, 10, 10, 10, 10, 10, 10, 10,
+     0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT: [i32; 64] = [
+   -50,-40,-30,-30,-30,-30,-40,-50,
+   -40,-20,  0,  0,  0,  0,-20,-40,
+   -30,  0, 10, 15, 15, 10,  0,-30,
+   -30,  5, 15, 20, 20, 15,  5,-30,
+   -30,  0, 15, 20, 20, 15,  0,-30,
+   -30,  5, 10, 15, 15, 10,  5,-30,
+   -40,-20,  0,  5,  5,  0,-20,-40,
+   -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP: [i32; 64] = [
+   -20,-10,-10,-10,-10,-10,-10,-20,
+   -10,  0,  0,  0,  0,  0,  0,-10,
+   -10,  0,  5, 10, 10,  5,  0,-10,
+   -10,  5,  5, 10, 10,  5,  5,-10,
+   -10,  0, 10, 10, 10, 10,  0,-10,
+   -10, 10, 10, 10, 10, 10, 10,-10,
+   -10,  5,  0,  0,  0,  0,  5,-10,
+   -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN: [i32; 64] = [
+   -20,-10,-10, -5, -5,-10,-10,-20,
+   -10,  0,  0,  0,  0,  0,  0,-10,
+   -10,  0,  5,  5,  5,  5,  0,-10,
+    -5,  0,  5,  5,  5,  5,  0, -5,
+     0,  0,  5,  5,  5,  5,  0, -5,
+   -10,  5,  5,  5,  5,  5,  0,-10,
+   -10,  0,  5,  0,  0,  0,  0,-10,
+   -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING_MIDDLEGAME: [i32; 64] = [
+   -30,-40,-40,-50,-50,-40,-40,-30,
+   -30,-40,-40,-50,-50,-40,-40,-30,
+   -30,-40,-40,-50,-50,-40,-40,-30,
+   -30,-40,-40,-50,-50,-40,-40,-30,
+   -20,-30,-30,-40,-40,-30,-30,-20,
+   -10,-20,-20,-20,-20,-20,-20,-10,
+    20, 20,  0,  0,  0,  0, 20, 20,
+    20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+#[rustfmt::skip]
+const KING_ENDGAME: [i32; 64] = [
+   -50,-40,-30,-20,-20,-30,-40,-50,
+   -30,-20,-10,  0,  0,-10,-20,-30,
+   -30,-10, 20, 30, 30, 20,-10,-30,
+   -30,-10, 30, 40, 40, 30,-10,-30,
+   -30,-10, 30, 40, 40, 30,-10,-30,
+   -30,-10, 20, 30, 30, 20,-10,-30,
+   -30,-30,  0,  0,  0,  0,-30,-30,
+   -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+const DOUBLED_PAWN: Tapered = Tapered(-10, -20);
+const ISOLATED_PAWN: Tapered = Tapered(-10, -15);
+const PASSED_PAWN: [Tapered; 8] = [
+    Tapered(0, 0),
+    Tapered(5, 10),
+    Tapered(10, 20),
+    Tapered(15, 30),
+    Tapered(25, 50),
+    Tapered(40, 80),
+    Tapered(60, 120),
+    Tapered(0, 0),
+];
+const PAWN_SHIELD: i32 = 10;
+const OPEN_FILE_NEAR_KING: i32 = -15;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Tapered(i32, i32);
+
+impl Tapered {
+    fn taper(self, phase: i32) -> i32 {
+        (self.0 * phase + self.1 * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Tapered {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Tapered(self.0 + other.0, self.1 + other.1)
+    }
+}
+
+impl AddAssign for Tapered {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Mul<i32> for Tapered {
+    type Output = Self;
+
+    fn mul(self, factor: i32) -> Self {
+        Tapered(self.0 * factor, self.1 * factor)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Evaluation {
+    pub phase: i32,
+    pub material: i32,
+    pub piece_squares: i32,
+    pub pawn_structure: i32,
+    pub king_safety: i32,
+    pub mobility: i32,
+}
+
+impl Evaluation {
+    pub fn total(&self) -> i32 {
+        self.terms().iter().map(|(_, score)| score).sum()
+    }
+
+    pub fn terms(&self) -> [(&'static str, i32); 5] {
+        [
+            ("material", self.material),
+            ("piece squares", self.piece_squares),
+            ("pawn structure", self.pawn_structure),
+            ("king safety", self.king_safety),
+            ("mobility", self.mobility),
+        ]
+    }
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "phase          {:>6} / {MAX_PHASE}", self.phase)?;
+        for (name, score) in self.terms() {
+            writeln!(f, "{name:<14} {score:>6}")?;
+        }
+        write!(f, "total          {:>6}", self.total())
+    }
+}
+
+pub fn piece_value(state: PieceState) -> i32 {
+    match state {
+        PieceState::Pawn => 100,
+        PieceState::Knight => 320,
+        PieceState::Bishop => 330,
+        PieceState::Rook => 500,
+        PieceState::Queen => 900,
+        PieceState::King => 0,
+    }
+}
+
+pub fn evaluate(gamestate: &GameState) -> i32 {
+    evaluate_terms(gamestate).total()
+}
+
+pub fn evaluate_terms(gamestate: &GameState) -> Evaluation {
+    let phase = game_phase(gamestate);
+    let mut evaluation = Evaluation {
+        phase,
+        ..Evaluation::default()
+    };
+    for color in [Color::White, Color::Black] {
+        let sign = if color == Color::White { 1 } else { -1 };
+        evaluation.material += sign * material(gamestate, color);
+        evaluation.piece_squares += sign * piece_squares(gamestate, color).taper(phase);
+        evaluation.pawn_structure += sign * pawn_structure(gamestate, color).taper(phase);
+        evaluation.king_safety += sign * Tapered(king_safety(gamestate, color), 0).taper(phase);
+        evaluation.mobility += sign * mobility(gamestate, color).taper(phase);
+    }
+    evaluation
+}
+
+fn pieces_of(gamestate: &GameState, color: Color) -> impl Iterator<Item = (Point, Piece)> + '_ {
+    gamestate
+        .pieces(color)
+        .iter()
+        .filter_map(|point| gamestate.get_piece(*point).map(|piece| (*point, piece)))
+}
+
+fn game_phase(gamestate: &GameState) -> i32 {
+    let phase = [Color::White, Color::Black]
+        .into_iter()
+        .flat_map(|color| pieces_of(gamestate, color))
+        .map(|(_, piece)| match piece.state {
+            PieceState::Knight | PieceState::Bishop => 1,
+            PieceState::Rook => 2,
+            PieceState::Queen => 4,
+            PieceState::Pawn | PieceState::King => 0,
+        })
+        .sum::<i32>();
+    phase.min(MAX_PHASE)
+}
+
+fn material(gamestate: &GameState, color: Color) -> i32 {
+    let mut bishops = 0;
+    let mut material = 0;
+    for (_, piece) in pieces_of(gamestate, color) {
+        material += piece_value(piece.state);
+        if piece.state == PieceState::Bishop {
+            bishops += 1;
+        }
+    }
+    if bishops >= 2 {
+        material += BISHOP_PAIR;
+    }
+    material
+}
+
+fn piece_squares(gamestate: &GameState, color: Color) -> Tapered {
+    let mut score = Tapered::default();
+    for ((x, y), piece) in pieces_of(gamestate, color) {
+        let rank = if color == Color::White {
+            x
+        } else {
+            BOARD_LENGTH - 1 - x
+        };
+        let square = (rank * BOARD_LENGTH + y) as usize;
+        score += match piece.state {
+            PieceState::Pawn => Tapered(PAWN_MIDDLEGAME[square], PAWN_ENDGAME[square]),
+            PieceState::Knight => Tapered(KNIGHT[square], KNIGHT[square]),
+            PieceState::Bishop => Tapered(BISHOP[square], BISHOP[square]),
+            PieceState::Rook => Tapered(ROOK[square], ROOK[square]),
+            PieceState::Queen => Tapered(QUEEN[square], QUEEN[square]),
+            PieceState::King => Tapered(KING_MIDDLEGAME[square], KING_ENDGAME[square]),
+        };
+    }
+    score
+}
+
+fn pawns(gamestate: &GameState, color: Color) -> Vec<Point> {
+    pieces_of(gamestate, color)
+        .filter(|(_, piece)| piece.state == PieceState::Pawn)
+        .map(|(point, _)| point)
+        .collect()
+}
+
+fn pawn_structure(gamestate: &GameState, color: Color) -> Tapered {
+    let ours = pawns(gamestate, color);
+    let theirs = pawns(gamestate, color.opposite());
+    let forward = if color == Color::White { -1 } else { 1 };
+
+    let mut files = [0; BOARD_LENGTH as usize];
+    for (_, y) in &ours {
+        files[*y as usize] += 1;
+    }
+
+    let mut score = Tapered::default();
+    for count in files {
+        if count > 1 {
+            score += DOUBLED_PAWN * (count - 1);
+        }
+    }
+    for &(x, y) in &ours {
+        let neighbours = [y - 1, y + 1]
+            .into_iter()
+            .filter(|file| (0..BOARD_LENGTH).contains(file))
+            .map(|file| files[file as usize])
+            .sum::<i32>();
+        if neighbours == 0 {
+            score += ISOLATED_PAWN;
+        }
+
+        let blocked = theirs
+            .iter()
+            .any(|&(their_x, their_y)| (their_y - y).abs() <= 1 && (their_x - x) * forward > 0);
+        if !blocked {
+            let advanced = flip_rank(x, color == Color::Black);
+            score += PASSED_PAWN[advanced as usize];
+        }
+    }
+    score
+}
+
+fn king_safety(gamestate: &GameState, color: Color) -> i32 {
+    let Some((king, _)) =
+        pieces_of(gamestate, color).find(|(_, piece)| piece.state == PieceState::King)
+    else {
+        return 0;
+    };
+    let ours = pawns(gamestate, color);
+    let forward = if color == Color::White { -1 } else { 1 };
+
+    let mut score = 0;
+    for file in king.1 - 1..=king.1 + 1 {
+        if !(0..BOARD_LENGTH).contains(&file) {
+            continue;
+        }
+        let shield = ours
+            .iter()
+            .any(|&(x, y)| y == file && (1..=2).contains(&((x - king.0) * forward)));
+        if shield {
+            score += PAWN_SHIELD;
+        }
+        if !ours.iter().any(|&(_, y)| y == file) {
+            score += OPEN_FILE_NEAR_KING;
+        }
+    }
+    score
+}
+
+fn mobility(gamestate: &GameState, color: Color) -> Tapered {
+    let occupancy = |color: Color| -> Bitboard {
+        gamestate
+            .pieces(color)
+            .iter()
+            .fold(0, |bitboard, point| bitboard | 1 << point_to_index(*point))
+    };
+    let ours = occupancy(color);
+    let occupied = ours | occupancy(color.opposite());
+
+    let mut score = Tapered::default();
+    for (point, piece) in pieces_of(gamestate, color) {
+        let square = point_to_index(point);
+        let (attacks, baseline, weight) = match piece.state {
+            PieceState::Knight => (knight_attacks(square), 4, Tapered(4, 4)),
+            PieceState::Bishop => (bishop_attacks(square, occupied), 7, Tapered(5, 5)),
+            PieceState::Rook => (rook_attacks(square, occupied), 7, Tapered(2, 4)),
+            PieceState::Queen => (
+                rook_attacks(square, occupied) | bishop_attacks(square, occupied),
+                14,
+                Tapered(1, 2),
+            ),
+            PieceState::Pawn | PieceState::King => continue,
+        };
+        score += weight * ((attacks & !ours).count_ones() as i32 - baseline);
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(fen: &str) -> Evaluation {
+        evaluate_terms(&GameState::from_fen(fen).expect("valid FEN"))
+    }
+
+    fn mirror(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let swap_case = |text: &str| {
+            text.chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect::<String>()
+        };
+        let placement = fields[0].split('/').rev().collect::<Vec<_>>().join("/");
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        format!(
+            "{} {side} {} - {} {}",
+            swap_case(&placement),
+            swap_case(fields[2]),
+            fields[4],
+            fields[5]
+        )
+    }
+
+    #[test]
+    fn start_position_is_balanced() {
+        let evaluation = evaluate_terms(&GameState::new());
+        assert_eq!(evaluation.phase, MAX_PHASE);
+        assert_eq!(
+            evaluation,
+            Evaluation {
+                phase: MAX_PHASE,
+                ..Evaluation::default()
+            }
+        );
+    }
+
+    #[test]
+    fn mirrored_positions_score_opposite() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            let evaluation = terms(fen);
+            let mirrored = terms(&mirror(fen));
+            assert_eq!(mirrored.material, -evaluation.material, "{fen}");
+            assert_eq!(mirrored.piece_squares, -evaluation.piece_squares, "{fen}");
+            assert_eq!(mirrored.pawn_structure, -evaluation.pawn_structure, "{fen}");
+            assert_eq!(mirrored.king_safety, -evaluation.king_safety, "{fen}");
+            assert_eq!(mirrored.mobility, -evaluation.mobility, "{fen}");
+        }
+    }
+
+    #[test]
+    fn material_counts_pieces_and_the_bishop_pair() {
+        assert_eq!(terms("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").material, 900);
+        assert_eq!(terms("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1").material, 690);
+    }
+
+    #[test]
+    fn pawn_structure_terms() {
+        let doubled_isolated = terms("4k3/8/8/8/8/4P3/4P3/4K3 w - - 0 1");
+        let connected = terms("4k3/8/8/8/8/8/3PP3/4K3 w - - 0 1");
+        assert!(doubled_isolated.pawn_structure < connected.pawn_structure);
+
+        let passed = terms("4k3/8/3P4/8/8/8/8/4K3 w - - 0 1");
+        let blocked = terms("4k3/4p3/3P4/8/8/8/8/4K3 w - - 0 1");
+        assert!(passed.pawn_structure > 0);
+        assert!(passed.pawn_structure > blocked.pawn_structure);
+
+        let seventh = terms("4k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        let sixth = terms("4k3/8/P7/8/8/8/8/4K3 w - - 0 1");
+        assert!(seventh.pawn_structure > sixth.pawn_structure);
+    }
+
+    #[test]
+    fn king_safety_rewards_a_pawn_shield() {
+        let sheltered = terms("r2qk3/8/8/8/8/8/5PPP/3Q1RK1 w - - 0 1");
+        let exposed = terms("r2qk3/8/8/5PPP/8/8/8/3Q1RK1 w - - 0 1");
+        assert!(sheltered.king_safety > exposed.king_safety);
+    }
+
+    #[test]
+    fn mobility_rewards_active_pieces() {
+        let centralised = terms("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1");
+        let cornered = terms("4k3/8/8/8/8/8/8/N3K3 w - - 0 1");
+        assert!(centralised.mobility > cornered.mobility);
+    }
+}
@@ -0,0 +1,171 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    WrongFieldCount(usize),
+    InvalidPiecePlacement(String),
+    InvalidKings,
+    PawnOnBackRank,
+    OpponentInCheck,
+    InvalidSideToMove(String),
+    InvalidCastlingRights(String),
+    InvalidEnPassant(String),
+    EnPassantWithoutPawn(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use FenError::*;
+        match self {
+            WrongFieldCount(count) => write!(f, "expected 6 FEN fields, found {count}"),
+            InvalidPiecePlacement(placement) => {
+                write!(f, "invalid piece placement \"{placement}\"")
+            }
+            InvalidKings => write!(f, "each side must have exactly one king"),
+            PawnOnBackRank => write!(f, "pawns cannot stand on the first or eighth rank"),
+            OpponentInCheck => write!(f, "the side not to move is in check"),
+            InvalidSideToMove(side) => write!(f, "invalid side to move \"{side}\""),
+            InvalidCastlingRights(castling) => {
+                write!(f, "invalid castling rights \"{castling}\"")
+            }
+            InvalidEnPassant(square) => write!(f, "invalid en passant square \"{square}\""),
+            EnPassantWithoutPawn(square) => {
+                write!(f, "no pawn has just passed en passant square \"{square}\"")
+            }
+            InvalidHalfmoveClock(clock) => write!(f, "invalid halfmove clock \"{clock}\""),
+            InvalidFullmoveNumber(number) => write!(f, "invalid fullmove number \"{number}\""),
+        }
+    }
+}
+
+impl Error for FenError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::Color;
+    use crate::GameState;
+
+    #[test]
+    fn round_trips_positions() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 5 20",
+            "8/8/4k3/8/8/3K4/8/8 w - - 99 140",
+        ] {
+            assert_eq!(GameState::from_fen(fen).unwrap().to_fen(), fen);
+        }
+        assert_eq!(
+            GameState::new().to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn reads_castling_rights_and_en_passant() {
+        let gamestate = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b Kq - 5 20").unwrap();
+        assert!(gamestate.can_castle(Color::White, true));
+        assert!(!gamestate.can_castle(Color::White, false));
+        assert!(!gamestate.can_castle(Color::Black, true));
+        assert!(gamestate.can_castle(Color::Black, false));
+        assert_eq!(gamestate.players_turn(), Color::Black);
+        assert_eq!(gamestate.halfmove_clock(), 5);
+        assert_eq!(gamestate.fullmove_number(), 20);
+
+        let mut gamestate = GameState::new();
+        gamestate.move_piece(gamestate.parse_san("e4").unwrap());
+        assert_eq!(
+            gamestate.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        let en_passant =
+            GameState::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3")
+                .unwrap();
+        assert_eq!(en_passant.en_passant(), Some((2, 5)));
+        assert_eq!(en_passant.parse_san("exf6").unwrap().to_string(), "e5f6");
+    }
+
+    #[test]
+    fn rejects_malformed_fen() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
+        let cases = [
+            (format!("{start} w KQkq -"), FenError::WrongFieldCount(4)),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1".to_string(),
+                FenError::InvalidPiecePlacement("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP".to_string()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+                FenError::InvalidPiecePlacement(
+                    "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR".to_string(),
+                ),
+            ),
+            (
+                "rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+                FenError::InvalidPiecePlacement(
+                    "rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR".to_string(),
+                ),
+            ),
+            (
+                "rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1".to_string(),
+                FenError::InvalidKings,
+            ),
+            (
+                "P3k3/8/8/8/8/8/8/4K3 w - - 0 1".to_string(),
+                FenError::PawnOnBackRank,
+            ),
+            (
+                "4k3/8/8/8/8/8/8/p3K3 b - - 0 1".to_string(),
+                FenError::PawnOnBackRank,
+            ),
+            (
+                "4k3/4R3/8/8/8/8/8/4K3 w - - 0 1".to_string(),
+                FenError::OpponentInCheck,
+            ),
+            (
+                format!("{start} x KQkq - 0 1"),
+                FenError::InvalidSideToMove("x".to_string()),
+            ),
+            (
+                format!("{start} w KQkx - 0 1"),
+                FenError::InvalidCastlingRights("KQkx".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w K - 0 1".to_string(),
+                FenError::InvalidCastlingRights("K".to_string()),
+            ),
+            (
+                format!("{start} w KQkq e3 0 1"),
+                FenError::InvalidEnPassant("e3".to_string()),
+            ),
+            (
+                format!("{start} w KQkq z9 0 1"),
+                FenError::InvalidEnPassant("z9".to_string()),
+            ),
+            (
+                format!("{start} w KQkq e6 0 1"),
+                FenError::EnPassantWithoutPawn("e6".to_string()),
+            ),
+            (
+                "4k3/8/8/8/3P4/8/8/4K3 b - e3 0 1".to_string(),
+                FenError::EnPassantWithoutPawn("e3".to_string()),
+            ),
+            (
+                format!("{start} w KQkq - -1 1"),
+                FenError::InvalidHalfmoveClock("-1".to_string()),
+            ),
+            (
+                format!("{start} w KQkq - 0 0"),
+                FenError::InvalidFullmoveNumber("0".to_string()),
+            ),
+        ];
+        for (fen, error) in cases {
+            assert_eq!(GameState::from_fen(&fen).err(), Some(error), "{fen}");
+        }
+    }
+}
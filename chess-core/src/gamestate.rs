@@ -0,0 +1,1365 @@
+use std::cell::OnceCell;
+use std::collections::HashSet;
+use std::time::Instant;
+
+use crate::bitboard::Position;
+use crate::clock::{Clock, TimeControl};
+use crate::fen::FenError;
+use crate::zobrist::piece_key;
+use crate::{util::*, BOARD_LENGTH};
+
+const FIFTY_MOVE_PLIES: i32 = 100;
+const SEVENTY_FIVE_MOVE_PLIES: i32 = 150;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndoInfo {
+    moved: (Point, Piece),
+    captured: Option<(Point, Piece)>,
+    castled_rook: Option<(Point, Point, Piece)>,
+    destination: Point,
+    en_passant: Option<Point>,
+    moves_since: i32,
+    fullmove_number: i32,
+    last_move: Option<Move>,
+    hash: u64,
+}
+
+#[derive(Clone)]
+pub struct GameState {
+    pub board: Board,
+    pub last_move: Option<Move>,
+    players_turn: Color,
+    en_passant: Option<Point>,
+    white_pieces: HashSet<Point>,
+    black_pieces: HashSet<Point>,
+    moves_since: i32,
+    fullmove_number: i32,
+    starting_fen: Option<String>,
+    history: Vec<PlayedMove>,
+    redo_stack: Vec<PlayedMove>,
+    hash: u64,
+    positions: Vec<u64>,
+    repetition_rule: RepetitionRule,
+    outcome: Option<PlayStatus>,
+    clock: Option<Clock>,
+    /// Bitboards for the current position, built on first use and cleared
+    /// whenever a move is made or unmade.
+    position: OnceCell<Position>,
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GameState {
+    pub fn new() -> Self {
+        let mut game_state = Self {
+            board: Vec::new(),
+            last_move: None,
+            players_turn: Color::White,
+            en_passant: None,
+            white_pieces: HashSet::new(),
+            black_pieces: HashSet::new(),
+            moves_since: 0,
+            fullmove_number: 1,
+            starting_fen: None,
+            history: Vec::new(),
+            redo_stack: Vec::new(),
+            hash: 0,
+            positions: Vec::new(),
+            repetition_rule: RepetitionRule::Threefold,
+            outcome: None,
+            clock: None,
+            position: OnceCell::new(),
+        };
+        game_state.intialise_new_board();
+        game_state
+    }
+
+    pub fn from_fen(fen: &str) -> Result<Self, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() != 6 {
+            return Err(FenError::WrongFieldCount(fields.len()));
+        }
+        let [placement, side, castling, en_passant, halfmove, fullmove] = [
+            fields[0], fields[1], fields[2], fields[3], fields[4], fields[5],
+        ];
+
+        let mut game_state = Self::new();
+        game_state.board = vec![vec![Tile::Empty; BOARD_LENGTH as usize]; BOARD_LENGTH as usize];
+        game_state.white_pieces.clear();
+        game_state.black_pieces.clear();
+
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != BOARD_LENGTH as usize {
+            return Err(FenError::InvalidPiecePlacement(placement.to_string()));
+        }
+        for (x, rank) in ranks.into_iter().enumerate() {
+            let mut y = 0;
+            for c in rank.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    y += empty as i32;
+                    continue;
+                }
+                let state = PieceState::from_char(c)
+                    .ok_or_else(|| FenError::InvalidPiecePlacement(placement.to_string()))?;
+                if !in_bounds((x as i32, y)) {
+                    return Err(FenError::InvalidPiecePlacement(placement.to_string()));
+                }
+                if state == PieceState::Pawn && (x == 0 || x == BOARD_LENGTH as usize - 1) {
+                    return Err(FenError::PawnOnBackRank);
+                }
+                let black = c.is_ascii_lowercase();
+                let mut piece = Piece::new(state, black);
+                piece.has_moved = match state {
+                    PieceState::Pawn => x as i32 != flip_rank(1, black),
+                    PieceState::King | PieceState::Rook => true,
+                    _ => false,
+                };
+                game_state.add_tile(x as i32, y, Tile::Piece(piece));
+                y += 1;
+            }
+            if y != BOARD_LENGTH {
+                return Err(FenError::InvalidPiecePlacement(placement.to_string()));
+            }
+        }
+
+        for color in [Color::White, Color::Black] {
+            let pieces = if color == Color::Black {
+                &game_state.black_pieces
+            } else {
+                &game_state.white_pieces
+            };
+            let kings = pieces
+                .iter()
+                .filter(|(x, y)| {
+                    matches!(
+                        game_state.board[*x as usize][*y as usize],
+                        Tile::Piece(Piece {
+                            state: PieceState::King,
+                            ..
+                        })
+                    )
+                })
+                .count();
+            if kings != 1 {
+                return Err(FenError::InvalidKings);
+            }
+        }
+
+        game_state.players_turn = match side {
+            "w" => Color::White,
+            "b" => Color::Black,
+            _ => return Err(FenError::InvalidSideToMove(side.to_string())),
+        };
+        if Position::new(&game_state).opponent_in_check() {
+            return Err(FenError::OpponentInCheck);
+        }
+
+        if castling != "-" {
+            for c in castling.chars() {
+                let black = c.is_ascii_lowercase();
+                let rook_file = match c.to_ascii_uppercase() {
+                    'K' => BOARD_LENGTH - 1,
+                    'Q' => 0,
+                    _ => return Err(FenError::InvalidCastlingRights(castling.to_string())),
+                };
+                let back_rank = flip_rank(0, black);
+                let color = if black { Color::Black } else { Color::White };
+                for (file, state) in [(4, PieceState::King), (rook_file, PieceState::Rook)] {
+                    match game_state.board[back_rank as usize][file as usize] {
+                        Tile::Piece(ref mut piece)
+                            if piece.state == state && piece.color == color =>
+                        {
+                            piece.has_moved = false;
+                        }
+                        _ => return Err(FenError::InvalidCastlingRights(castling.to_string())),
+                    }
+                }
+            }
+        }
+
+        if en_passant != "-" {
+            let point = square_to_point(en_passant)
+                .ok_or_else(|| FenError::InvalidEnPassant(en_passant.to_string()))?;
+            let black_moved = game_state.players_turn == Color::White;
+            if point.0 != flip_rank(2, black_moved) {
+                return Err(FenError::InvalidEnPassant(en_passant.to_string()));
+            }
+            let pushed = (flip_rank(3, black_moved), point.1);
+            let mover = game_state.players_turn.opposite();
+            if !matches!(
+                game_state.get_piece(pushed),
+                Some(Piece { state: PieceState::Pawn, color, .. }) if color == mover
+            ) {
+                return Err(FenError::EnPassantWithoutPawn(en_passant.to_string()));
+            }
+            game_state.en_passant = Some(point);
+        }
+
+        game_state.moves_since = halfmove
+            .parse()
+            .ok()
+            .filter(|clock| *clock >= 0)
+            .ok_or_else(|| FenError::InvalidHalfmoveClock(halfmove.to_string()))?;
+        game_state.fullmove_number = fullmove
+            .parse()
+            .ok()
+            .filter(|number| *number >= 1)
+            .ok_or_else(|| FenError::InvalidFullmoveNumber(fullmove.to_string()))?;
+        game_state.starting_fen = Some(fen.to_string());
+        game_state.hash = game_state.compute_hash();
+        game_state.positions = vec![game_state.hash];
+        game_state.position.take();
+
+        Ok(game_state)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut placement = Vec::new();
+        for rank in &self.board {
+            let mut row = String::new();
+            let mut empty = 0;
+            for tile in rank {
+                match tile {
+                    Tile::Empty => empty += 1,
+                    Tile::Piece(piece) => {
+                        if empty > 0 {
+                            row.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        let c = piece.state.to_char();
+                        row.push(if piece.color == Color::Black {
+                            c.to_ascii_lowercase()
+                        } else {
+                            c
+                        });
+                    }
+                }
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            placement.push(row);
+        }
+
+        let side = if self.players_turn == Color::Black {
+            "b"
+        } else {
+            "w"
+        };
+
+        let en_passant = self.en_passant.map_or("-".to_string(), point_to_square);
+
+        format!(
+            "{} {} {} {} {} {}",
+            placement.join("/"),
+            side,
+            self.castling_rights(),
+            en_passant,
+            self.moves_since,
+            self.fullmove_number
+        )
+    }
+
+    fn castling_rights(&self) -> String {
+        let mut rights = String::new();
+        for color in [Color::White, Color::Black] {
+            for (kingside, right) in [(true, 'K'), (false, 'Q')] {
+                if self.can_castle(color, kingside) {
+                    rights.push(if color == Color::Black {
+                        right.to_ascii_lowercase()
+                    } else {
+                        right
+                    });
+                }
+            }
+        }
+        if rights.is_empty() {
+            rights.push('-');
+        }
+        rights
+    }
+
+    pub fn can_castle(&self, color: Color, kingside: bool) -> bool {
+        let back_rank = flip_rank(0, color == Color::Black) as usize;
+        let unmoved = |file: usize, state: PieceState| {
+            matches!(
+                self.board[back_rank][file],
+                Tile::Piece(piece) if piece.state == state && piece.color == color && !piece.has_moved
+            )
+        };
+        let rook_file = if kingside {
+            BOARD_LENGTH as usize - 1
+        } else {
+            0
+        };
+        unmoved(4, PieceState::King) && unmoved(rook_file, PieceState::Rook)
+    }
+
+    pub fn is_empty(&self, (x, y): Point) -> bool {
+        Tile::Empty == self.board[x as usize][y as usize]
+    }
+
+    /// Sets up the standard starting position for a new game, keeping the
+    /// repetition rule and time control.
+    pub fn intialise_new_board(&mut self) {
+        self.board = vec![vec![Tile::Empty; BOARD_LENGTH as usize]; BOARD_LENGTH as usize];
+        self.white_pieces.clear();
+        self.black_pieces.clear();
+        self.last_move = None;
+        self.players_turn = Color::White;
+        self.en_passant = None;
+        self.moves_since = 0;
+        self.fullmove_number = 1;
+        self.starting_fen = None;
+        self.history.clear();
+        self.redo_stack.clear();
+        self.outcome = None;
+        self.clock = self
+            .clock
+            .take()
+            .map(|clock| Clock::new(clock.control().clone()));
+        for black in [true, false] {
+            let back_rank = flip_rank(0, black);
+            let pawn_rank = flip_rank(1, black);
+
+            for file in 0..BOARD_LENGTH {
+                self.add_tile(
+                    back_rank,
+                    file,
+                    Tile::Piece(Piece::new(file_to_piece(file), black)),
+                );
+                self.add_tile(
+                    pawn_rank,
+                    file,
+                    Tile::Piece(Piece::new(PieceState::Pawn, black)),
+                );
+            }
+        }
+        self.hash = self.compute_hash();
+        self.positions = vec![self.hash];
+        self.position.take();
+    }
+
+    pub fn end_game(&self) -> PlayStatus {
+        use PlayStatus::*;
+        if let Some(outcome) = self.outcome {
+            return outcome;
+        }
+        let position = self.position();
+        let no_legal_moves = position.legal_moves().is_empty();
+        if no_legal_moves && position.in_check() {
+            return Win(self.players_turn.opposite(), GameEndReason::Checkmate);
+        }
+
+        if self.repetition_count() >= self.repetition_rule.automatic_draw() {
+            return Draw(GameEndReason::Repetition);
+        }
+        if self.seventy_five_move_rule() {
+            return Draw(GameEndReason::SeventyFiveMoveRule);
+        }
+        if no_legal_moves {
+            return Draw(GameEndReason::Stalemate);
+        }
+        if self.insufficient_material() {
+            return Draw(GameEndReason::InsufficientMaterial);
+        }
+
+        Continue
+    }
+
+    /// Records a timeout if the running clock has run out by `now`. Called
+    /// whenever the clock is pressed and on every tick of the game loop.
+    pub fn check_clock(&mut self, now: Instant) {
+        let Some(color) = self.clock.as_ref().and_then(|clock| clock.flagged(now)) else {
+            return;
+        };
+        if self.end_game() == PlayStatus::Continue {
+            self.time_out(color);
+        }
+    }
+
+    pub fn set_time_control(&mut self, control: TimeControl) {
+        self.clock = Some(Clock::new(control));
+    }
+
+    pub fn clock(&self) -> Option<&Clock> {
+        self.clock.as_ref()
+    }
+    pub fn set_repetition_rule(&mut self, repetition_rule: RepetitionRule) {
+        self.repetition_rule = repetition_rule;
+    }
+
+    pub fn repetition_count(&self) -> usize {
+        let current = self
+            .positions
+            .last()
+            .expect("starting position is recorded");
+        self.positions
+            .iter()
+            .filter(|position| *position == current)
+            .count()
+    }
+
+    pub fn can_claim_draw(&self) -> bool {
+        self.repetition_count() >= 3 || self.fifty_move_rule()
+    }
+
+    pub fn claim_draw(&mut self) -> bool {
+        if !self.can_claim_draw() {
+            return false;
+        }
+        let reason = if self.repetition_count() >= 3 {
+            GameEndReason::Repetition
+        } else {
+            GameEndReason::FiftyMoveRule
+        };
+        self.finish(PlayStatus::Draw(reason));
+        true
+    }
+
+    pub fn resign(&mut self) {
+        self.finish(PlayStatus::Win(
+            self.players_turn.opposite(),
+            GameEndReason::Resignation,
+        ));
+    }
+
+    pub fn agree_draw(&mut self) {
+        self.finish(PlayStatus::Draw(GameEndReason::Agreement));
+    }
+
+    /// Records that `color` ran out of time.
+    pub fn time_out(&mut self, color: Color) {
+        self.finish(self.timeout_outcome(color));
+    }
+
+    fn timeout_outcome(&self, color: Color) -> PlayStatus {
+        let opponent = color.opposite();
+        if self.cannot_mate(opponent) {
+            PlayStatus::Draw(GameEndReason::Timeout)
+        } else {
+            PlayStatus::Win(opponent, GameEndReason::Timeout)
+        }
+    }
+
+    fn finish(&mut self, outcome: PlayStatus) {
+        self.outcome = Some(outcome);
+        if let Some(clock) = self.clock.as_mut() {
+            clock.stop(Instant::now());
+        }
+    }
+
+    pub fn players_turn(&self) -> Color {
+        self.players_turn
+    }
+
+    pub fn fullmove_number(&self) -> i32 {
+        self.fullmove_number
+    }
+
+    pub fn starting_fen(&self) -> Option<&str> {
+        self.starting_fen.as_deref()
+    }
+
+    pub fn history(&self) -> &[PlayedMove] {
+        &self.history
+    }
+
+    pub fn get_piece(&self, (x, y): Point) -> Option<Piece> {
+        match self.board[x as usize][y as usize] {
+            Tile::Empty => None,
+            Tile::Piece(piece) => Some(piece),
+        }
+    }
+
+    pub fn en_passant(&self) -> Option<Point> {
+        self.en_passant
+    }
+
+    pub fn pieces(&self, color: Color) -> &HashSet<Point> {
+        if color == Color::Black {
+            &self.black_pieces
+        } else {
+            &self.white_pieces
+        }
+    }
+
+    pub fn piece_legal_moves(&self, point: Point) -> HashSet<Point> {
+        let mut moves = HashSet::new();
+        for Move { src, dst, .. } in self.legal_moves() {
+            if src != point {
+                continue;
+            }
+            moves.insert(dst);
+            if self
+                .get_piece(src)
+                .is_some_and(|piece| piece.state == PieceState::King)
+                && (dst.1 - src.1).abs() == 2
+            {
+                moves.insert((dst.0, if dst.1 > src.1 { BOARD_LENGTH - 1 } else { 0 }));
+            }
+        }
+        moves
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        self.position().legal_moves()
+    }
+
+    pub fn in_check(&self) -> bool {
+        self.position().in_check()
+    }
+
+    pub(crate) fn position(&self) -> &Position {
+        self.position.get_or_init(|| Position::new(self))
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn position_hashes(&self) -> &[u64] {
+        &self.positions
+    }
+
+    pub fn halfmove_clock(&self) -> i32 {
+        self.moves_since
+    }
+
+    pub fn get_king(&self) -> Point {
+        self.get_king_color(self.players_turn)
+    }
+
+    fn get_king_color(&self, color: Color) -> Point {
+        let pieces = if color == Color::Black {
+            &self.black_pieces
+        } else {
+            &self.white_pieces
+        };
+        for (x, y) in pieces {
+            if let Tile::Piece(Piece {
+                state: PieceState::King,
+                ..
+            }) = self.board[*x as usize][*y as usize]
+            {
+                return (*x, *y);
+            }
+        }
+        panic!("No king on board!")
+    }
+
+    pub fn is_promotion(
+        &self,
+        Move {
+            src: (x, y), dst, ..
+        }: &Move,
+    ) -> bool {
+        let promotion_rank = flip_rank(0, self.players_turn == Color::White);
+        matches!(
+            self.board[*x as usize][*y as usize],
+            Tile::Piece(Piece {
+                state: PieceState::Pawn,
+                ..
+            })
+        ) && dst.0 == promotion_rank
+    }
+
+    fn insufficient_material(&self) -> bool {
+        let mut minor_pieces = Vec::new();
+        for point @ (x, y) in self.white_pieces.iter().chain(&self.black_pieces) {
+            match self.get_piece(*point).map(|piece| piece.state) {
+                Some(PieceState::King) => {}
+                Some(state @ (PieceState::Bishop | PieceState::Knight)) => {
+                    minor_pieces.push((state, (x + y) % 2))
+                }
+                _ => return false,
+            }
+        }
+
+        match minor_pieces[..] {
+            [] | [_] => true,
+            [(_, square_color), ..] => minor_pieces
+                .iter()
+                .all(|(state, color)| *state == PieceState::Bishop && *color == square_color),
+        }
+    }
+
+    /// Whether no sequence of legal moves lets `color` mate: it has a bare
+    /// king, or the whole board is down to insufficient material.
+    fn cannot_mate(&self, color: Color) -> bool {
+        self.pieces(color).len() == 1 || self.insufficient_material()
+    }
+
+    fn fifty_move_rule(&self) -> bool {
+        self.moves_since >= FIFTY_MOVE_PLIES
+    }
+
+    fn seventy_five_move_rule(&self) -> bool {
+        self.moves_since >= SEVENTY_FIVE_MOVE_PLIES
+    }
+
+    fn make_tile_empty(&mut self, x: i32, y: i32) -> bool {
+        let occupied =
+            if let Tile::Piece(piece @ Piece { color, .. }) = self.board[x as usize][y as usize] {
+                self.hash ^= piece_key(piece, (x, y));
+                if color == Color::Black {
+                    self.black_pieces.remove(&(x, y));
+                } else {
+                    self.white_pieces.remove(&(x, y));
+                }
+                true
+            } else {
+                false
+            };
+
+        self.board[x as usize][y as usize] = Tile::Empty;
+        occupied
+    }
+
+    fn add_tile(&mut self, x: i32, y: i32, tile: Tile) -> bool {
+        let occupied = self.make_tile_empty(x, y);
+        if let Tile::Piece(piece @ Piece { color, .. }) = tile {
+            self.hash ^= piece_key(piece, (x, y));
+            if color == Color::Black {
+                self.black_pieces.insert((x, y));
+            } else {
+                self.white_pieces.insert((x, y));
+            }
+        }
+        self.board[x as usize][y as usize] = tile;
+        occupied
+    }
+
+    /// Turns a king moving onto its own unmoved corner rook, on the same
+    /// rank, into the two-square king move that castles with that rook.
+    pub(crate) fn king_takes_rook_castle(&self, Move { src, dst, .. }: Move) -> Option<Move> {
+        let king = self.get_piece(src)?;
+        let rook = self.get_piece(dst)?;
+        let corner = dst.1 == 0 || dst.1 == BOARD_LENGTH - 1;
+        if king.state != PieceState::King
+            || rook.state != PieceState::Rook
+            || rook.color != king.color
+            || rook.has_moved
+            || dst.0 != src.0
+            || !corner
+        {
+            return None;
+        }
+        let direction = (dst.1 - src.1).signum();
+        Some(Move::new(src, (src.0, src.1 + 2 * direction)))
+    }
+
+    /// Plays `selected_move` if it is legal and the game has not been
+    /// decided, accepting king-takes-own-rook castling input. Returns whether
+    /// the move was played.
+    pub fn move_piece(&mut self, mut selected_move: Move) -> bool {
+        if self.outcome.is_some() {
+            return false;
+        }
+        if self.is_promotion(&selected_move) && selected_move.promotion.is_none() {
+            return false;
+        }
+        if let Some(castle) = self.king_takes_rook_castle(selected_move) {
+            selected_move = castle;
+        }
+        if !self.legal_moves().contains(&selected_move) {
+            return false;
+        }
+        let mover = self.players_turn;
+        let mut san = selected_move.san_move(self);
+        self.make_move(selected_move);
+        san.push_str(self.check_suffix());
+        self.push_played(PlayedMove {
+            played: selected_move,
+            san,
+        });
+        self.redo_stack.clear();
+
+        let finished = self.clock.is_some() && self.end_game() != PlayStatus::Continue;
+        let now = Instant::now();
+        if let Some(clock) = self.clock.as_mut() {
+            clock.press(mover, now);
+            if finished {
+                clock.stop(now);
+            }
+        }
+        self.check_clock(now);
+        true
+    }
+
+    pub fn position_after(&self, plies: usize) -> GameState {
+        let mut position = match self.starting_fen {
+            Some(ref fen) => GameState::from_fen(fen).expect("starting FEN was parsed before"),
+            None => GameState::new(),
+        };
+        position.repetition_rule = self.repetition_rule;
+        for played in self.history.iter().take(plies) {
+            position.record_move(played.clone());
+        }
+        position
+    }
+
+    pub fn first_move(&self) -> (i32, Color) {
+        match self.starting_fen {
+            Some(ref fen) => {
+                let start = GameState::from_fen(fen).expect("starting FEN was parsed before");
+                (start.fullmove_number(), start.players_turn())
+            }
+            None => (1, Color::White),
+        }
+    }
+
+    /// Takes back the last move. Games ended by resignation, agreement, a
+    /// claim or a timeout stay decided, so nothing is undone.
+    pub fn undo(&mut self) -> bool {
+        if self.outcome.is_some() {
+            return false;
+        }
+        let Some(undone) = self.history.pop() else {
+            return false;
+        };
+        let mut redo_stack = std::mem::take(&mut self.redo_stack);
+        redo_stack.push(undone);
+
+        let mut restored = self.position_after(self.history.len());
+        restored.redo_stack = redo_stack;
+        restored.clock = self.clock.take();
+        *self = restored;
+        self.hand_clock_to_mover();
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        if self.outcome.is_some() {
+            return false;
+        }
+        let Some(redone) = self.redo_stack.pop() else {
+            return false;
+        };
+        self.record_move(redone);
+        self.hand_clock_to_mover();
+        true
+    }
+
+    fn hand_clock_to_mover(&mut self) {
+        if let Some(clock) = self.clock.as_mut() {
+            clock.switch_to(self.players_turn, Instant::now());
+        }
+    }
+
+    fn record_move(&mut self, played: PlayedMove) {
+        self.make_move(played.played);
+        self.push_played(played);
+    }
+
+    fn push_played(&mut self, played: PlayedMove) {
+        self.history.push(played);
+        self.positions.push(self.hash);
+    }
+
+    /// Plays a move without checking it; callers pass moves from
+    /// `legal_moves`.
+    pub(crate) fn make_move(
+        &mut self,
+        selected_move @ Move {
+            src: src @ (src_x, src_y),
+            dst: dst @ (dst_x, dst_y),
+            promotion,
+        }: Move,
+    ) -> UndoInfo {
+        let piece = self.get_piece(src).expect("no piece on the source square");
+        self.position.take();
+        let hash = self.hash;
+        self.hash ^= self.state_key();
+        let mut undo = UndoInfo {
+            moved: (src, piece),
+            captured: None,
+            castled_rook: None,
+            en_passant: self.en_passant.take(),
+            moves_since: self.moves_since,
+            fullmove_number: self.fullmove_number,
+            last_move: self.last_move,
+            hash,
+            destination: dst,
+        };
+        self.last_move = Some(selected_move);
+
+        self.moves_since += 1;
+        if self.players_turn == Color::Black {
+            self.fullmove_number += 1;
+        }
+
+        if piece.state == PieceState::Pawn {
+            self.moves_since = 0;
+
+            if Some(dst) == undo.en_passant {
+                let direction = if self.players_turn == Color::Black {
+                    -1
+                } else {
+                    1
+                };
+                let captured = (dst_x + direction, dst_y);
+                undo.captured = self.get_piece(captured).map(|piece| (captured, piece));
+                self.make_tile_empty(captured.0, captured.1);
+            }
+
+            if (dst_x - src_x).abs() == 2 {
+                self.en_passant = Some(((src_x + dst_x) / 2, src_y));
+            }
+        }
+
+        if piece.state == PieceState::King && (dst_y - src_y).abs() > 1 {
+            let file = if dst_y > src_y { BOARD_LENGTH - 1 } else { 0 };
+            if let Some(rook) = self.get_piece((dst_x, file)) {
+                let direction = if dst_y > src_y { 1 } else { -1 };
+                let rook_dst = (src_x, src_y + direction);
+                let king_dst = (src_x, src_y + 2 * direction);
+
+                self.make_tile_empty(src_x, src_y);
+                self.make_tile_empty(dst_x, file);
+                self.add_tile(
+                    rook_dst.0,
+                    rook_dst.1,
+                    Tile::Piece(Piece {
+                        has_moved: true,
+                        ..rook
+                    }),
+                );
+                self.add_tile(
+                    king_dst.0,
+                    king_dst.1,
+                    Tile::Piece(Piece {
+                        has_moved: true,
+                        ..piece
+                    }),
+                );
+
+                undo.castled_rook = Some(((dst_x, file), rook_dst, rook));
+                undo.destination = king_dst;
+                self.change_players_turn();
+                self.last_move = Some(Move::new(src, (dst_x, file)));
+                self.hash ^= self.state_key();
+                return undo;
+            }
+        }
+
+        let promotion = if self.is_promotion(&selected_move) {
+            promotion.or(Some(PieceState::Queen))
+        } else {
+            None
+        };
+        if let Some(captured) = self.get_piece(dst) {
+            undo.captured = Some((dst, captured));
+            self.moves_since = 0;
+        }
+        self.add_tile(
+            dst_x,
+            dst_y,
+            Tile::Piece(Piece {
+                state: promotion.unwrap_or(piece.state),
+                has_moved: true,
+                ..piece
+            }),
+        );
+        self.make_tile_empty(src_x, src_y);
+
+        self.change_players_turn();
+        self.hash ^= self.state_key();
+        undo
+    }
+
+    pub(crate) fn unmake_move(&mut self, undo: UndoInfo) {
+        self.position.take();
+        self.change_players_turn();
+
+        self.make_tile_empty(undo.destination.0, undo.destination.1);
+        if let Some((rook_src, rook_dst, rook)) = undo.castled_rook {
+            self.make_tile_empty(rook_dst.0, rook_dst.1);
+            self.add_tile(rook_src.0, rook_src.1, Tile::Piece(rook));
+        }
+        if let Some(((x, y), captured)) = undo.captured {
+            self.add_tile(x, y, Tile::Piece(captured));
+        }
+        let ((x, y), moved) = undo.moved;
+        self.add_tile(x, y, Tile::Piece(moved));
+
+        self.en_passant = undo.en_passant;
+        self.moves_since = undo.moves_since;
+        self.fullmove_number = undo.fullmove_number;
+        self.last_move = undo.last_move;
+        self.hash = undo.hash;
+    }
+
+    fn change_players_turn(&mut self) {
+        self.players_turn = if self.players_turn == Color::Black {
+            Color::White
+        } else {
+            Color::Black
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(gamestate: &mut GameState, moves: &[&str]) {
+        for san in moves {
+            let selected_move = gamestate.parse_san(san).expect("legal test move");
+            gamestate.move_piece(selected_move);
+        }
+    }
+
+    fn castling_moves(fen: &str) -> Vec<&'static str> {
+        let gamestate = GameState::from_fen(fen).expect("valid FEN");
+        let king = gamestate.get_king();
+        let legal_moves = gamestate.piece_legal_moves(king);
+        [("O-O", 2), ("O-O-O", -2)]
+            .into_iter()
+            .filter(|(_, offset)| legal_moves.contains(&(king.0, king.1 + offset)))
+            .map(|(castle, _)| castle)
+            .collect()
+    }
+
+    #[test]
+    fn castling_on_both_sides() {
+        assert_eq!(
+            castling_moves("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"),
+            ["O-O", "O-O-O"]
+        );
+        assert_eq!(
+            castling_moves("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1"),
+            ["O-O", "O-O-O"]
+        );
+    }
+
+    #[test]
+    fn no_castling_out_of_check() {
+        assert!(castling_moves("4k3/8/8/8/8/8/4r3/R3K2R w KQ - 0 1").is_empty());
+        assert!(castling_moves("4k3/8/8/8/8/3n4/8/R3K2R w KQ - 0 1").is_empty());
+    }
+
+    #[test]
+    fn no_castling_through_check() {
+        assert_eq!(
+            castling_moves("4k3/8/8/8/8/8/6b1/R3K2R w KQ - 0 1"),
+            ["O-O-O"]
+        );
+        assert_eq!(castling_moves("3rk3/8/8/8/8/8/8/R3K2R w KQ - 0 1"), ["O-O"]);
+    }
+
+    #[test]
+    fn no_castling_into_check() {
+        assert_eq!(
+            castling_moves("4k1r1/8/8/8/8/8/8/R3K2R w KQ - 0 1"),
+            ["O-O-O"]
+        );
+        assert_eq!(
+            castling_moves("r3k2r/8/8/8/8/8/8/2R1K3 b kq - 0 1"),
+            ["O-O"]
+        );
+    }
+
+    #[test]
+    fn pawns_attack_empty_squares_next_to_the_king() {
+        assert!(castling_moves("4k3/8/8/8/8/8/4p3/R3K2R w KQ - 0 1").is_empty());
+        assert_eq!(
+            castling_moves("r3k2r/6P1/8/8/8/8/8/4K3 b kq - 0 1"),
+            ["O-O-O"]
+        );
+    }
+
+    #[test]
+    fn queenside_castling_only_needs_b_file_empty() {
+        assert_eq!(
+            castling_moves("1r2k3/8/8/8/8/8/8/R3K2R w KQ - 0 1"),
+            ["O-O", "O-O-O"]
+        );
+        assert_eq!(castling_moves("4k3/8/8/8/8/8/8/RN2K2R w KQ - 0 1"), ["O-O"]);
+        assert_eq!(castling_moves("4k3/8/8/8/8/8/8/R2QK2R w KQ - 0 1"), ["O-O"]);
+    }
+
+    #[test]
+    fn castling_needs_unmoved_king_and_rook() {
+        assert_eq!(castling_moves("4k3/8/8/8/8/8/8/R3K2R w K - 0 1"), ["O-O"]);
+        assert!(castling_moves("4k3/8/8/8/8/8/8/R3K2R w - - 0 1").is_empty());
+        assert_eq!(
+            castling_moves("4k3/8/8/8/8/8/8/R3K1NR w KQ - 0 1"),
+            ["O-O-O"]
+        );
+    }
+
+    #[test]
+    fn castling_moves_rook_and_removes_rights() {
+        let mut gamestate =
+            GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").expect("valid FEN");
+        play(&mut gamestate, &["O-O", "O-O-O"]);
+        assert_eq!(gamestate.to_fen(), "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2");
+    }
+
+    #[test]
+    fn halfmove_clock_resets_on_pawn_moves_and_captures() {
+        let mut gamestate = GameState::new();
+        play(&mut gamestate, &["Nf3", "Nc6"]);
+        assert_eq!(gamestate.moves_since, 2);
+        play(&mut gamestate, &["e4"]);
+        assert_eq!(gamestate.moves_since, 0);
+        play(&mut gamestate, &["Nb4", "Nc3", "Nxa2"]);
+        assert_eq!(gamestate.moves_since, 0);
+        play(&mut gamestate, &["Bc4"]);
+        assert_eq!(gamestate.moves_since, 1);
+    }
+
+    #[test]
+    fn halfmove_clock_resets_on_en_passant() {
+        let mut gamestate =
+            GameState::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 7 30").expect("valid FEN");
+        play(&mut gamestate, &["exd6"]);
+        assert_eq!(gamestate.moves_since, 0);
+    }
+
+    #[test]
+    fn fifty_moves_are_claimable_but_not_automatic() {
+        let mut gamestate =
+            GameState::from_fen("4k3/8/8/8/8/8/3R4/4K3 w - - 99 80").expect("valid FEN");
+        assert!(!gamestate.can_claim_draw());
+        play(&mut gamestate, &["Ra2"]);
+        assert_eq!(gamestate.end_game(), PlayStatus::Continue);
+        assert!(gamestate.claim_draw());
+        assert_eq!(
+            gamestate.end_game(),
+            PlayStatus::Draw(GameEndReason::FiftyMoveRule)
+        );
+    }
+
+    #[test]
+    fn seventy_five_moves_draw_automatically() {
+        let mut gamestate =
+            GameState::from_fen("4k3/8/8/8/8/8/3R4/4K3 w - - 149 100").expect("valid FEN");
+        assert_eq!(gamestate.end_game(), PlayStatus::Continue);
+        play(&mut gamestate, &["Ra2"]);
+        assert_eq!(
+            gamestate.end_game(),
+            PlayStatus::Draw(GameEndReason::SeventyFiveMoveRule)
+        );
+    }
+
+    #[test]
+    fn insufficient_material_draws_automatically() {
+        let end_game = |fen: &str| GameState::from_fen(fen).expect("valid FEN").end_game();
+        let draw = PlayStatus::Draw(GameEndReason::InsufficientMaterial);
+        assert_eq!(end_game("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), draw);
+        assert_eq!(end_game("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1"), draw);
+        assert_eq!(end_game("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1"), draw);
+        assert_eq!(end_game("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1"), draw);
+        assert_eq!(
+            end_game("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1"),
+            PlayStatus::Continue
+        );
+        assert_eq!(
+            end_game("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"),
+            PlayStatus::Continue
+        );
+    }
+
+    #[test]
+    fn capturing_the_last_pawn_leaves_insufficient_material() {
+        let mut gamestate =
+            GameState::from_fen("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1").expect("valid FEN");
+        assert_eq!(gamestate.end_game(), PlayStatus::Continue);
+        play(&mut gamestate, &["Kxd2"]);
+        assert_eq!(
+            gamestate.end_game(),
+            PlayStatus::Draw(GameEndReason::InsufficientMaterial)
+        );
+    }
+
+    #[test]
+    fn checkmate_takes_precedence_over_seventy_five_moves() {
+        let mut gamestate =
+            GameState::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 149 100").expect("valid FEN");
+        play(&mut gamestate, &["Ra8#"]);
+        assert_eq!(
+            gamestate.end_game(),
+            PlayStatus::Win(Color::White, GameEndReason::Checkmate)
+        );
+    }
+
+    #[test]
+    fn flag_fall_loses_unless_the_opponent_cannot_mate() {
+        let out_of_time = TimeControl::sudden_death(std::time::Duration::ZERO);
+        let mut gamestate = GameState::new();
+        gamestate.set_time_control(out_of_time.clone());
+        assert_eq!(gamestate.end_game(), PlayStatus::Continue);
+        play(&mut gamestate, &["e4"]);
+        assert_eq!(
+            gamestate.end_game(),
+            PlayStatus::Win(Color::White, GameEndReason::Timeout)
+        );
+
+        let flag_fall = |fen: &str, san: &str| {
+            let mut gamestate = GameState::from_fen(fen).expect("valid FEN");
+            gamestate.set_time_control(out_of_time.clone());
+            play(&mut gamestate, &[san]);
+            gamestate.end_game()
+        };
+        assert_eq!(
+            flag_fall("4k2r/8/8/8/8/8/8/4KN2 w - - 0 1", "Ng3"),
+            PlayStatus::Win(Color::White, GameEndReason::Timeout)
+        );
+        assert_eq!(
+            flag_fall("4k2r/8/8/8/8/8/8/4K3 w - - 0 1", "Kd2"),
+            PlayStatus::Draw(GameEndReason::Timeout)
+        );
+        assert_eq!(
+            flag_fall("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1", "Bd2"),
+            PlayStatus::Draw(GameEndReason::InsufficientMaterial)
+        );
+    }
+
+    #[test]
+    fn clock_ticks_record_the_flag() {
+        let mut gamestate = GameState::new();
+        gamestate.set_time_control(TimeControl::sudden_death(std::time::Duration::from_secs(
+            60,
+        )));
+        play(&mut gamestate, &["e4"]);
+        let later = Instant::now() + std::time::Duration::from_secs(61);
+        assert_eq!(gamestate.end_game(), PlayStatus::Continue);
+        gamestate.check_clock(later);
+        assert_eq!(
+            gamestate.end_game(),
+            PlayStatus::Win(Color::White, GameEndReason::Timeout)
+        );
+    }
+
+    #[test]
+    fn time_out_ends_the_game() {
+        let mut gamestate = GameState::new();
+        gamestate.set_time_control(TimeControl::sudden_death(std::time::Duration::from_secs(
+            60,
+        )));
+        play(&mut gamestate, &["e4"]);
+        assert_eq!(gamestate.clock().unwrap().running(), Some(Color::Black));
+        gamestate.time_out(Color::Black);
+        assert_eq!(
+            gamestate.end_game(),
+            PlayStatus::Win(Color::White, GameEndReason::Timeout)
+        );
+        assert_eq!(gamestate.clock().unwrap().running(), None);
+
+        let mut gamestate =
+            GameState::from_fen("4k3/8/8/8/8/8/8/4KB2 b - - 0 1").expect("valid FEN");
+        gamestate.time_out(Color::Black);
+        assert_eq!(
+            gamestate.end_game(),
+            PlayStatus::Draw(GameEndReason::Timeout)
+        );
+    }
+
+    #[test]
+    fn new_board_resets_the_game() {
+        let mut gamestate =
+            GameState::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 40").expect("valid FEN");
+        gamestate.set_time_control(TimeControl::sudden_death(std::time::Duration::ZERO));
+        play(&mut gamestate, &["Kd7", "e4"]);
+        gamestate.resign();
+        gamestate.intialise_new_board();
+
+        let fresh = GameState::new();
+        assert_eq!(gamestate.to_fen(), fresh.to_fen());
+        assert_eq!(gamestate.hash(), fresh.hash());
+        assert!(gamestate.history().is_empty());
+        assert_eq!(gamestate.starting_fen(), None);
+        assert_eq!(gamestate.repetition_count(), 1);
+        assert_eq!(gamestate.end_game(), PlayStatus::Continue);
+        assert_eq!(gamestate.clock().and_then(|clock| clock.running()), None);
+    }
+
+    #[test]
+    fn position_after_replays_the_opening_moves() {
+        let mut gamestate =
+            GameState::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 40").expect("valid FEN");
+        play(&mut gamestate, &["Kd7", "e4", "Kc6"]);
+        assert_eq!(gamestate.first_move(), (40, Color::Black));
+        assert_eq!(
+            gamestate.position_after(2).to_fen(),
+            "8/3k4/8/8/4P3/8/8/4K3 b - e3 0 41"
+        );
+        assert_eq!(
+            gamestate.position_after(0).to_fen(),
+            "4k3/8/8/8/8/8/4P3/4K3 b - - 0 40"
+        );
+        assert_eq!(gamestate.position_after(9).to_fen(), gamestate.to_fen());
+    }
+
+    const KNIGHT_SHUFFLE: [&str; 4] = ["Nf3", "Nf6", "Ng1", "Ng8"];
+
+    #[test]
+    fn threefold_repetition_draws_automatically_by_default() {
+        let mut gamestate = GameState::new();
+        play(&mut gamestate, &KNIGHT_SHUFFLE);
+        assert_eq!(gamestate.repetition_count(), 2);
+        assert!(!gamestate.claim_draw());
+        assert_eq!(gamestate.end_game(), PlayStatus::Continue);
+
+        play(&mut gamestate, &KNIGHT_SHUFFLE);
+        assert_eq!(gamestate.repetition_count(), 3);
+        assert_eq!(
+            gamestate.end_game(),
+            PlayStatus::Draw(GameEndReason::Repetition)
+        );
+    }
+
+    #[test]
+    fn fivefold_rule_makes_threefold_a_claim() {
+        let mut claimed = GameState::new();
+        claimed.set_repetition_rule(RepetitionRule::Fivefold);
+        play(&mut claimed, &KNIGHT_SHUFFLE);
+        play(&mut claimed, &KNIGHT_SHUFFLE);
+        assert_eq!(claimed.end_game(), PlayStatus::Continue);
+        assert!(claimed.can_claim_draw());
+        let mut automatic = claimed.clone();
+        assert!(claimed.claim_draw());
+        assert_eq!(
+            claimed.end_game(),
+            PlayStatus::Draw(GameEndReason::Repetition)
+        );
+
+        play(&mut automatic, &KNIGHT_SHUFFLE);
+        assert_eq!(automatic.repetition_count(), 4);
+        assert_eq!(automatic.end_game(), PlayStatus::Continue);
+        play(&mut automatic, &KNIGHT_SHUFFLE);
+        assert_eq!(automatic.repetition_count(), 5);
+        assert_eq!(
+            automatic.end_game(),
+            PlayStatus::Draw(GameEndReason::Repetition)
+        );
+    }
+
+    #[test]
+    fn repetitions_need_the_same_rights() {
+        let mut gamestate = GameState::new();
+        play(&mut gamestate, &["e4", "e5", "Ke2", "Ke7", "Ke1", "Ke8"]);
+        assert_eq!(gamestate.repetition_count(), 1);
+        play(&mut gamestate, &["Ke2", "Ke7", "Ke1", "Ke8"]);
+        assert_eq!(gamestate.repetition_count(), 2);
+    }
+
+    #[test]
+    fn undo_and_redo_restore_the_position() {
+        for (fen, san) in [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "e4",
+            ),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "O-O-O"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 3 9", "Kf8"),
+            (
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+                "exf6",
+            ),
+        ] {
+            let mut gamestate = GameState::from_fen(fen).expect("valid FEN");
+            play(&mut gamestate, &[san]);
+            let after = gamestate.to_fen();
+            let hash = gamestate.hash();
+
+            assert!(gamestate.undo());
+            assert_eq!(gamestate.to_fen(), fen, "undoing {san}");
+            assert!(gamestate.history().is_empty());
+            assert_eq!(gamestate.position_hashes(), [gamestate.hash()]);
+            assert!(!gamestate.undo());
+
+            assert!(gamestate.redo());
+            assert_eq!(gamestate.to_fen(), after, "redoing {san}");
+            assert_eq!(gamestate.hash(), hash);
+            assert!(!gamestate.redo());
+        }
+    }
+
+    #[test]
+    fn decided_games_cannot_be_taken_back() {
+        let mut resigned = GameState::new();
+        play(&mut resigned, &["e4", "e5"]);
+        assert!(resigned.undo());
+        resigned.resign();
+        assert!(!resigned.undo());
+        assert!(!resigned.redo());
+        let reply = resigned.parse_san("e5").unwrap();
+        assert!(!resigned.move_piece(reply));
+        assert_eq!(resigned.history().len(), 1);
+        assert_eq!(
+            resigned.end_game(),
+            PlayStatus::Win(Color::White, GameEndReason::Resignation)
+        );
+
+        let mut agreed = GameState::new();
+        play(&mut agreed, &["d4"]);
+        agreed.agree_draw();
+        assert!(!agreed.undo());
+        assert_eq!(
+            agreed.end_game(),
+            PlayStatus::Draw(GameEndReason::Agreement)
+        );
+
+        let mut flagged = GameState::new();
+        flagged.time_out(Color::White);
+        assert!(!flagged.move_piece(flagged.parse_san("e4").unwrap()));
+        assert!(flagged.history().is_empty());
+    }
+
+    #[test]
+    fn unmake_move_restores_the_position() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            let mut gamestate = GameState::from_fen(fen).expect("valid FEN");
+            let board = gamestate.board.clone();
+            let white_pieces = gamestate.white_pieces.clone();
+            let black_pieces = gamestate.black_pieces.clone();
+            let position = *gamestate.position();
+            for legal_move in gamestate.legal_moves() {
+                let undo = gamestate.make_move(legal_move);
+                assert_eq!(*gamestate.position(), Position::new(&gamestate));
+                gamestate.unmake_move(undo);
+                assert_eq!(*gamestate.position(), position, "after {legal_move}");
+                assert_eq!(gamestate.to_fen(), fen, "after {legal_move}");
+                assert_eq!(gamestate.board, board, "after {legal_move}");
+                assert_eq!(gamestate.white_pieces, white_pieces);
+                assert_eq!(gamestate.black_pieces, black_pieces);
+                assert_eq!(gamestate.last_move, None);
+            }
+        }
+    }
+
+    #[test]
+    fn move_piece_rejects_invalid_moves() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K2R w K - 0 1";
+        let mut gamestate = GameState::from_fen(fen).expect("valid FEN");
+        for invalid in [
+            Move::new((3, 3), (2, 3)),
+            Move::new((6, 4), (3, 4)),
+            Move::new((7, 4), (6, 4)),
+            Move::new((0, 4), (1, 4)),
+            Move::with_promotion((6, 4), (5, 4), PieceState::Queen),
+        ] {
+            assert!(!gamestate.move_piece(invalid), "{invalid}");
+            assert_eq!(gamestate.to_fen(), fen);
+            assert!(gamestate.history().is_empty());
+        }
+        assert!(gamestate.move_piece(Move::new((7, 4), (7, 7))));
+        assert_eq!(gamestate.to_fen(), "4k3/8/8/8/8/8/4P3/5RK1 b - - 1 1");
+
+        let fen = "4k3/8/8/8/8/8/3B4/R3K3 w Q - 0 1";
+        let mut gamestate = GameState::from_fen(fen).expect("valid FEN");
+        assert!(!gamestate.move_piece(Move::new((7, 4), (6, 3))));
+        assert_eq!(gamestate.to_fen(), fen);
+    }
+}
@@ -0,0 +1,18 @@
+pub const BOARD_LENGTH: i32 = 8;
+
+pub mod bitboard;
+pub mod clock;
+pub mod engine;
+pub mod eval;
+pub mod fen;
+pub mod gamestate;
+pub mod perft;
+pub mod pgn;
+pub mod polyglot;
+pub mod san;
+pub mod syzygy;
+pub mod uci;
+pub mod util;
+pub mod zobrist;
+
+pub use gamestate::GameState;
@@ -0,0 +1,600 @@
+use crate::fen::FenError;
+use crate::gamestate::GameState;
+use crate::san::SanError;
+use crate::util::*;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LINE_LENGTH: usize = 80;
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnError {
+    UnterminatedTag,
+    UnterminatedComment,
+    UnbalancedVariation,
+    InvalidFen(FenError),
+    InvalidMove { ply: usize, error: SanError },
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use PgnError::*;
+        match self {
+            UnterminatedTag => write!(f, "unterminated tag pair"),
+            UnterminatedComment => write!(f, "unterminated comment"),
+            UnbalancedVariation => write!(f, "unbalanced variation parentheses"),
+            InvalidFen(e) => write!(f, "invalid FEN tag: {e}"),
+            InvalidMove { ply, error } => write!(f, "{error} at ply {ply}"),
+        }
+    }
+}
+
+impl Error for PgnError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnMove {
+    pub played: Move,
+    pub san: String,
+    pub nags: Vec<u32>,
+    pub comment: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub start: GameState,
+    pub moves: Vec<PgnMove>,
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn positions(&self) -> Vec<GameState> {
+        let mut positions = vec![self.start.clone()];
+        for PgnMove { played, .. } in &self.moves {
+            let mut next = positions.last().expect("never empty").clone();
+            next.move_piece(*played);
+            positions.push(next);
+        }
+        positions
+    }
+}
+
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u32),
+    VariationStart,
+    VariationEnd,
+    Symbol(String),
+}
+
+impl GameState {
+    pub fn to_pgn(&self) -> String {
+        let result = result_tag(&self.end_game());
+        let mut pgn = String::new();
+        for (tag, value) in [
+            ("Event", "Casual Game".to_string()),
+            ("Site", "?".to_string()),
+            ("Date", today()),
+            ("Round", "-".to_string()),
+            ("White", "?".to_string()),
+            ("Black", "?".to_string()),
+            ("Result", result.to_string()),
+        ] {
+            pgn.push_str(&format!("[{tag} \"{value}\"]\n"));
+        }
+        if let Some(fen) = self.starting_fen() {
+            pgn.push_str("[SetUp \"1\"]\n");
+            pgn.push_str(&format!("[FEN \"{fen}\"]\n"));
+        }
+        pgn.push('\n');
+
+        let (mut move_number, first_color) = self.first_move();
+        let mut black_to_move = first_color == Color::Black;
+
+        let mut tokens = Vec::new();
+        for (i, PlayedMove { san, .. }) in self.history().iter().enumerate() {
+            if !black_to_move {
+                tokens.push(format!("{move_number}."));
+            } else if i == 0 {
+                tokens.push(format!("{move_number}..."));
+            }
+            tokens.push(san.clone());
+            if black_to_move {
+                move_number += 1;
+            }
+            black_to_move = !black_to_move;
+        }
+        tokens.push(result.to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+
+    pub fn save_pgn(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_pgn())
+    }
+}
+
+fn result_tag(status: &PlayStatus) -> &'static str {
+    match status {
+        PlayStatus::Continue => "*",
+        PlayStatus::Win(Color::White, _) => "1-0",
+        PlayStatus::Win(Color::Black, _) => "0-1",
+        PlayStatus::Draw(_) => "1/2-1/2",
+    }
+}
+
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() / 86400) as i64;
+    pgn_date(days)
+}
+
+/// Formats a day count since 1970-01-01 as a PGN date, using Howard
+/// Hinnant's days-to-civil conversion.
+fn pgn_date(days: i64) -> String {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}.{month:02}.{day:02}")
+}
+
+pub fn load_pgn(path: impl AsRef<Path>) -> Result<Vec<PgnGame>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse_pgn(&text).map_err(|e| e.to_string())
+}
+
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut games = Vec::new();
+    let mut tags = Vec::new();
+    let mut moves: Vec<PgnMove> = Vec::new();
+    let mut position: Option<GameState> = None;
+    let mut start: Option<GameState> = None;
+    let mut result: Option<String> = None;
+    let mut depth = 0;
+
+    for token in tokenize(text)? {
+        if depth > 0 {
+            match token {
+                Token::VariationStart => depth += 1,
+                Token::VariationEnd => depth -= 1,
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Token::Tag(..) = token {
+            if !moves.is_empty() || result.is_some() {
+                games.push(finish_game(
+                    std::mem::take(&mut tags),
+                    start.take(),
+                    std::mem::take(&mut moves),
+                    result.take(),
+                )?);
+                position = None;
+            }
+        } else if position.is_none() {
+            let initial = starting_position(&tags)?;
+            start = Some(initial.clone());
+            position = Some(initial);
+        }
+
+        match token {
+            Token::Tag(name, value) => tags.push((name, value)),
+            Token::Comment(comment) => {
+                if let Some(last) = moves.last_mut() {
+                    last.comment = Some(match last.comment.take() {
+                        Some(previous) => format!("{previous} {comment}"),
+                        None => comment,
+                    });
+                }
+            }
+            Token::Nag(nag) => {
+                if let Some(last) = moves.last_mut() {
+                    last.nags.push(nag);
+                }
+            }
+            Token::VariationStart => depth += 1,
+            Token::VariationEnd => return Err(PgnError::UnbalancedVariation),
+            Token::Symbol(symbol) if RESULTS.contains(&symbol.as_str()) => result = Some(symbol),
+            Token::Symbol(symbol) => {
+                let gamestate = position.as_mut().expect("set before the first move");
+                let played =
+                    gamestate
+                        .parse_san(&symbol)
+                        .map_err(|error| PgnError::InvalidMove {
+                            ply: moves.len() + 1,
+                            error,
+                        })?;
+                let san = played.to_san(gamestate);
+                gamestate.move_piece(played);
+                moves.push(PgnMove {
+                    played,
+                    san,
+                    nags: Vec::new(),
+                    comment: None,
+                });
+            }
+        }
+    }
+
+    if depth > 0 {
+        return Err(PgnError::UnbalancedVariation);
+    }
+    if !tags.is_empty() || !moves.is_empty() || result.is_some() {
+        games.push(finish_game(tags, start, moves, result)?);
+    }
+    Ok(games)
+}
+
+fn finish_game(
+    tags: Vec<(String, String)>,
+    start: Option<GameState>,
+    moves: Vec<PgnMove>,
+    result: Option<String>,
+) -> Result<PgnGame, PgnError> {
+    let start = match start {
+        Some(start) => start,
+        None => starting_position(&tags)?,
+    };
+    let result = result
+        .or_else(|| {
+            tags.iter()
+                .find(|(tag, _)| tag == "Result")
+                .map(|(_, value)| value.clone())
+        })
+        .unwrap_or_else(|| "*".to_string());
+    Ok(PgnGame {
+        tags,
+        start,
+        moves,
+        result,
+    })
+}
+
+fn starting_position(tags: &[(String, String)]) -> Result<GameState, PgnError> {
+    match tags.iter().find(|(tag, _)| tag == "FEN") {
+        Some((_, fen)) => GameState::from_fen(fen).map_err(PgnError::InvalidFen),
+        None => Ok(GameState::new()),
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        let column_one = std::mem::replace(&mut line_start, c == '\n');
+        match c {
+            c if c.is_whitespace() => {}
+            '[' => tokens.push(read_tag(&mut chars)?),
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err(PgnError::UnterminatedComment),
+                    }
+                }
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|c| *c != '\n').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+                line_start = true;
+            }
+            '%' if column_one => {
+                chars.by_ref().take_while(|c| *c != '\n').for_each(drop);
+                line_start = true;
+            }
+            '(' => tokens.push(Token::VariationStart),
+            ')' => tokens.push(Token::VariationEnd),
+            '$' => {
+                let nag = read_while(&mut chars, |c| c.is_ascii_digit());
+                tokens.push(Token::Nag(nag.parse().unwrap_or(0)));
+            }
+            c => {
+                let mut symbol = c.to_string();
+                symbol.push_str(&read_while(&mut chars, |c| {
+                    !c.is_whitespace() && !"[]{}();$".contains(c)
+                }));
+                push_symbol(&mut tokens, &symbol);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn push_symbol(tokens: &mut Vec<Token>, symbol: &str) {
+    if RESULTS.contains(&symbol) {
+        tokens.push(Token::Symbol(symbol.to_string()));
+        return;
+    }
+
+    let without_number = symbol.trim_start_matches(|c: char| c.is_ascii_digit());
+    let symbol = if without_number.starts_with('.') {
+        without_number.trim_start_matches('.')
+    } else {
+        symbol
+    };
+
+    let san = symbol.trim_end_matches(['!', '?']);
+    if !san.is_empty() {
+        tokens.push(Token::Symbol(san.to_string()));
+    }
+    let nag = match &symbol[san.len()..] {
+        "!" => 1,
+        "?" => 2,
+        "!!" => 3,
+        "??" => 4,
+        "!?" => 5,
+        "?!" => 6,
+        _ => return,
+    };
+    tokens.push(Token::Nag(nag));
+}
+
+fn read_tag(chars: &mut Peekable<Chars>) -> Result<Token, PgnError> {
+    let name = read_while(chars, |c| c != '"' && c != ']');
+    if chars.next() != Some('"') {
+        return Err(PgnError::UnterminatedTag);
+    }
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some('\\') => value.extend(chars.next()),
+            Some('"') => break,
+            Some(c) => value.push(c),
+            None => return Err(PgnError::UnterminatedTag),
+        }
+    }
+    read_while(chars, |c| c != ']');
+    if chars.next() != Some(']') {
+        return Err(PgnError::UnterminatedTag);
+    }
+    Ok(Token::Tag(name.trim().to_string(), value))
+}
+
+fn read_while(chars: &mut Peekable<Chars>, predicate: impl Fn(char) -> bool) -> String {
+    let mut read = String::new();
+    while let Some(c) = chars.next_if(|c| predicate(*c)) {
+        read.push(c);
+    }
+    read
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sans(game: &PgnGame) -> Vec<&str> {
+        game.moves
+            .iter()
+            .map(|played| played.san.as_str())
+            .collect()
+    }
+
+    const OPERA_GAME: &str = "e4 e5 Nf3 d6 d4 Bg4 dxe5 Bxf3 Qxf3 dxe5 Bc4 Nf6 Qb3 Qe7 Nc3 c6 \
+        Bg5 b5 Nxb5 cxb5 Bxb5+ Nbd7 O-O-O Rd8 Rxd7 Rxd7 Rd1 Qe6 Bxd7+ Nxd7 Qb8+ Nxb8 Rd8#";
+
+    fn played(gamestate: &mut GameState, moves: &str) {
+        for san in moves.split_whitespace() {
+            let selected_move = gamestate.parse_san(san).expect("legal test move");
+            assert!(gamestate.move_piece(selected_move));
+        }
+    }
+
+    fn tags(pgn: &str) -> Vec<&str> {
+        pgn.lines()
+            .take_while(|line| line.starts_with('['))
+            .map(|line| &line[1..line.find(' ').unwrap()])
+            .collect()
+    }
+
+    #[test]
+    fn exports_the_seven_tag_roster_and_wrapped_movetext() {
+        let mut gamestate = GameState::new();
+        played(&mut gamestate, OPERA_GAME);
+        let pgn = gamestate.to_pgn();
+        assert_eq!(
+            tags(&pgn),
+            ["Event", "Site", "Date", "Round", "White", "Black", "Result"]
+        );
+        assert!(pgn.contains("[Result \"1-0\"]\n\n"));
+
+        let movetext: Vec<&str> = pgn.lines().skip_while(|line| !line.is_empty()).collect();
+        assert_eq!(
+            movetext,
+            [
+                "",
+                "1. e4 e5 2. Nf3 d6 3. d4 Bg4 4. dxe5 Bxf3 5. Qxf3 dxe5 6. Bc4 Nf6 7. Qb3 Qe7 8.",
+                "Nc3 c6 9. Bg5 b5 10. Nxb5 cxb5 11. Bxb5+ Nbd7 12. O-O-O Rd8 13. Rxd7 Rxd7 14.",
+                "Rd1 Qe6 15. Bxd7+ Nxd7 16. Qb8+ Nxb8 17. Rd8# 1-0",
+            ]
+        );
+
+        let games = parse_pgn(&pgn).unwrap();
+        assert_eq!(
+            sans(&games[0]),
+            OPERA_GAME.split_whitespace().collect::<Vec<_>>()
+        );
+        assert_eq!(games[0].result, "1-0");
+    }
+
+    #[test]
+    fn exports_the_result_and_starting_position() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 40";
+        let mut gamestate = GameState::from_fen(fen).unwrap();
+        played(&mut gamestate, "Kd7 e4");
+        let pgn = gamestate.to_pgn();
+        assert_eq!(
+            tags(&pgn),
+            ["Event", "Site", "Date", "Round", "White", "Black", "Result", "SetUp", "FEN"]
+        );
+        assert!(pgn.contains(&format!("[FEN \"{fen}\"]")));
+        assert!(pgn.ends_with("\n40... Kd7 41. e4 *\n"));
+
+        gamestate.resign();
+        assert!(gamestate.to_pgn().ends_with("41. e4 1-0\n"));
+        let mut drawn = GameState::new();
+        drawn.agree_draw();
+        assert!(drawn.to_pgn().contains("[Result \"1/2-1/2\"]\n\n1/2-1/2\n"));
+    }
+
+    #[test]
+    fn dates_count_days_from_the_unix_epoch() {
+        assert_eq!(pgn_date(0), "1970.01.01");
+        assert_eq!(pgn_date(-1), "1969.12.31");
+        assert_eq!(pgn_date(11016), "2000.02.29");
+        assert_eq!(pgn_date(11017), "2000.03.01");
+        assert_eq!(pgn_date(20088), "2024.12.31");
+        assert_eq!(pgn_date(47541), "2100.03.01");
+        let today = today();
+        assert_eq!(today.len(), 10);
+        assert_eq!(today.matches('.').count(), 2);
+    }
+
+    #[test]
+    fn reads_tags_moves_and_result() {
+        let games = parse_pgn(
+            "[Event \"Club \\\"Open\\\"\"]\n[White \"Anna\"]\n[Result \"1-0\"]\n\n\
+             1. e4 e5 2.Nf3 Nc6 3. Bb5 a6 1-0\n",
+        )
+        .unwrap();
+        assert_eq!(games.len(), 1);
+        let game = &games[0];
+        assert_eq!(game.tag("Event"), Some("Club \"Open\""));
+        assert_eq!(game.tag("White"), Some("Anna"));
+        assert_eq!(game.tag("Black"), None);
+        assert_eq!(sans(game), ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+        assert_eq!(game.result, "1-0");
+        assert_eq!(game.positions().len(), 7);
+    }
+
+    #[test]
+    fn attaches_comments_and_nags_to_moves() {
+        let games = parse_pgn(
+            "1. e4 {best by test} e5 $2 ; a rest-of-line comment\n\
+             2. Nf3!? Nc6?? {a blunder} {really} *",
+        )
+        .unwrap();
+        let moves = &games[0].moves;
+        assert_eq!(moves[0].comment.as_deref(), Some("best by test"));
+        assert_eq!(moves[1].nags, [2]);
+        assert_eq!(moves[1].comment.as_deref(), Some("a rest-of-line comment"));
+        assert_eq!(moves[2].san, "Nf3");
+        assert_eq!(moves[2].nags, [5]);
+        assert_eq!(moves[3].nags, [4]);
+        assert_eq!(moves[3].comment.as_deref(), Some("a blunder really"));
+        assert_eq!(games[0].result, "*");
+    }
+
+    #[test]
+    fn skips_nested_variations() {
+        let games = parse_pgn(
+            "1. e4 (1. d4 d5 (1... Nf6 2. c4) 2. c4) 1... c5 (1... e5 2. Nf3) 2. Nf3 1/2-1/2",
+        )
+        .unwrap();
+        assert_eq!(sans(&games[0]), ["e4", "c5", "Nf3"]);
+        assert_eq!(games[0].result, "1/2-1/2");
+    }
+
+    #[test]
+    fn reads_several_games_and_fen_starts() {
+        let games = parse_pgn(
+            "[White \"A\"]\n\n1. d4 0-1\n\n\
+             [SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]\n\n\
+             40... Kd7 41. e4 *\n",
+        )
+        .unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(sans(&games[0]), ["d4"]);
+        assert_eq!(games[0].result, "0-1");
+        assert_eq!(sans(&games[1]), ["Kd7", "e4"]);
+        assert_eq!(games[1].start.to_fen(), "4k3/8/8/8/8/8/4P3/4K3 b - - 0 40");
+    }
+
+    #[test]
+    fn escapes_lines_starting_with_percent() {
+        let games = parse_pgn("% exported by some tool\n1. e4 e5\n%ignored 2. d4\n*").unwrap();
+        assert_eq!(sans(&games[0]), ["e4", "e5"]);
+
+        assert!(matches!(
+            parse_pgn("1. e4 %e5\n*"),
+            Err(PgnError::InvalidMove { ply: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn reports_malformed_input() {
+        assert_eq!(
+            parse_pgn("[Event \"Open").err(),
+            Some(PgnError::UnterminatedTag)
+        );
+        assert_eq!(
+            parse_pgn("[Event Open]").err(),
+            Some(PgnError::UnterminatedTag)
+        );
+        assert_eq!(
+            parse_pgn("1. e4 {never closed").err(),
+            Some(PgnError::UnterminatedComment)
+        );
+        assert_eq!(
+            parse_pgn("1. e4 (1. d4 *").err(),
+            Some(PgnError::UnbalancedVariation)
+        );
+        assert_eq!(
+            parse_pgn("1. e4 ) e5 *").err(),
+            Some(PgnError::UnbalancedVariation)
+        );
+        assert!(matches!(
+            parse_pgn("[FEN \"8/8/8 w - - 0 1\"]\n1. e4 *"),
+            Err(PgnError::InvalidFen(FenError::InvalidPiecePlacement(_)))
+        ));
+        assert_eq!(
+            parse_pgn("1. e4 e5 2. Ke3 *").err(),
+            Some(PgnError::InvalidMove {
+                ply: 3,
+                error: SanError::Illegal("Ke3".to_string()),
+            })
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::gamestate::GameState;
+use crate::util::*;
+use crate::BOARD_LENGTH;
+
+const ENTRY_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    key: u64,
+    raw_move: u16,
+    weight: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookMove {
+    pub played: Move,
+    pub weight: u16,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Book {
+    entries: Vec<Entry>,
+}
+
+impl Book {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::from_bytes(&fs::read(path)?))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut entries: Vec<Entry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|chunk| Entry {
+                key: u64::from_be_bytes(chunk[0..8].try_into().expect("8 bytes")),
+                raw_move: u16::from_be_bytes([chunk[8], chunk[9]]),
+                weight: u16::from_be_bytes([chunk[10], chunk[11]]),
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.key);
+        Self { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn moves(&self, gamestate: &GameState) -> Vec<BookMove> {
+        let key = gamestate.hash();
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| {
+                decode_move(gamestate, entry.raw_move).map(|played| BookMove {
+                    played,
+                    weight: entry.weight,
+                })
+            })
+            .collect()
+    }
+
+    pub fn choose(&self, gamestate: &GameState, random: u64) -> Option<Move> {
+        let moves = self.moves(gamestate);
+        let total: u64 = moves.iter().map(|book_move| book_move.weight as u64).sum();
+        if total == 0 {
+            return moves.first().map(|book_move| book_move.played);
+        }
+        let mut target = random % total;
+        for book_move in moves {
+            if target < book_move.weight as u64 {
+                return Some(book_move.played);
+            }
+            target -= book_move.weight as u64;
+        }
+        None
+    }
+}
+
+fn decode_move(gamestate: &GameState, raw_move: u16) -> Option<Move> {
+    let square = |bits: u16| -> Point {
+        let file = (bits & 7) as i32;
+        let rank = ((bits >> 3) & 7) as i32;
+        (BOARD_LENGTH - 1 - rank, file)
+    };
+    let src = square(raw_move >> 6);
+    let dst = square(raw_move);
+    let promotion = match (raw_move >> 12) & 7 {
+        0 => None,
+        1 => Some(PieceState::Knight),
+        2 => Some(PieceState::Bishop),
+        3 => Some(PieceState::Rook),
+        4 => Some(PieceState::Queen),
+        _ => return None,
+    };
+
+    let dst = gamestate
+        .king_takes_rook_castle(Move::new(src, dst))
+        .map_or(dst, |castle| castle.dst);
+
+    gamestate.legal_moves().into_iter().find(|legal_move| {
+        legal_move.src == src && legal_move.dst == dst && legal_move.promotion == promotion
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(gamestate: &GameState, text: &str, weight: u16) -> Vec<u8> {
+        let square = |name: &str| {
+            let (x, y) = square_to_point(name).expect("valid square");
+            ((BOARD_LENGTH - 1 - x) * 8 + y) as u16
+        };
+        let promotion = match text.chars().nth(4) {
+            Some('n') => 1,
+            Some('b') => 2,
+            Some('r') => 3,
+            Some('q') => 4,
+            _ => 0,
+        };
+        let raw_move = promotion << 12 | square(&text[0..2]) << 6 | square(&text[2..4]);
+        let mut bytes = gamestate.hash().to_be_bytes().to_vec();
+        bytes.extend(raw_move.to_be_bytes());
+        bytes.extend(weight.to_be_bytes());
+        bytes.extend(0u32.to_be_bytes());
+        bytes
+    }
+
+    fn moves(book: &Book, gamestate: &GameState) -> Vec<(String, u16)> {
+        book.moves(gamestate)
+            .into_iter()
+            .map(|book_move| (book_move.played.to_string(), book_move.weight))
+            .collect()
+    }
+
+    #[test]
+    fn looks_up_moves_by_position() {
+        let start = GameState::new();
+        let mut after_e4 = GameState::new();
+        after_e4.move_piece(after_e4.parse_uci_move("e2e4").unwrap());
+
+        let bytes = [
+            entry(&after_e4, "c7c5", 5),
+            entry(&start, "e2e4", 3),
+            entry(&start, "d2d4", 1),
+        ]
+        .concat();
+        let book = Book::from_bytes(&bytes);
+        assert_eq!(book.len(), 3);
+        assert_eq!(
+            moves(&book, &start),
+            [("e2e4".to_string(), 3), ("d2d4".to_string(), 1)]
+        );
+        assert_eq!(moves(&book, &after_e4), [("c7c5".to_string(), 5)]);
+        assert!(book
+            .moves(&GameState::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn reads_entries_keyed_by_published_polyglot_hashes() {
+        let mut bytes = 0x463b_9618_1691_fc9c_u64.to_be_bytes().to_vec();
+        bytes.extend([0x03, 0x1c, 0x00, 0x07, 0, 0, 0, 0]);
+        let book = Book::from_bytes(&bytes);
+        assert_eq!(moves(&book, &GameState::new()), [("e2e4".to_string(), 7)]);
+    }
+
+    #[test]
+    fn chooses_moves_by_weight() {
+        let start = GameState::new();
+        let book = Book::from_bytes(&[entry(&start, "e2e4", 3), entry(&start, "d2d4", 1)].concat());
+        let choose = |random| book.choose(&start, random).unwrap().to_string();
+        assert_eq!(choose(0), "e2e4");
+        assert_eq!(choose(2), "e2e4");
+        assert_eq!(choose(3), "d2d4");
+        assert_eq!(choose(4), "e2e4");
+    }
+
+    #[test]
+    fn decodes_castling_and_promotions() {
+        let castling = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let promotion = GameState::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let book = Book::from_bytes(
+            &[
+                entry(&castling, "e1h1", 1),
+                entry(&castling, "e1a1", 1),
+                entry(&promotion, "b7b8n", 1),
+            ]
+            .concat(),
+        );
+        assert_eq!(
+            moves(&book, &castling),
+            [("e1g1".to_string(), 1), ("e1c1".to_string(), 1)]
+        );
+        assert_eq!(moves(&book, &promotion), [("b7b8n".to_string(), 1)]);
+    }
+
+    #[test]
+    fn skips_illegal_entries() {
+        let start = GameState::new();
+        let book = Book::from_bytes(&entry(&start, "e2e5", 1));
+        assert!(book.moves(&start).is_empty());
+        assert_eq!(book.choose(&start, 0), None);
+
+        let bishop = GameState::from_fen("4k3/8/8/8/8/8/3B4/R3K3 w Q - 0 1").unwrap();
+        let book = Book::from_bytes(&entry(&bishop, "e1d2", 1));
+        assert!(book.moves(&bishop).is_empty());
+    }
+}
@@ -0,0 +1,281 @@
+use crate::gamestate::GameState;
+use crate::util::*;
+use crate::BOARD_LENGTH;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    Invalid(String),
+    Illegal(String),
+    Ambiguous(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SanError::*;
+        match self {
+            Invalid(san) => write!(f, "\"{san}\" is not valid SAN"),
+            Illegal(san) => write!(f, "\"{san}\" is not a legal move"),
+            Ambiguous(san) => write!(f, "\"{san}\" matches more than one legal move"),
+        }
+    }
+}
+
+impl Error for SanError {}
+
+impl GameState {
+    pub(crate) fn check_suffix(&self) -> &'static str {
+        let position = self.position();
+        if !position.in_check() {
+            ""
+        } else if position.legal_moves().is_empty() {
+            "#"
+        } else {
+            "+"
+        }
+    }
+
+    pub fn parse_san(&self, san: &str) -> Result<Move, SanError> {
+        let invalid = || SanError::Invalid(san.to_string());
+        let text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+
+        let castling = text.replace('0', "O");
+        if castling == "O-O" || castling == "O-O-O" {
+            let king = self.get_king();
+            let direction = if castling == "O-O" { 1 } else { -1 };
+            return self
+                .legal_moves()
+                .into_iter()
+                .find(|candidate| {
+                    candidate.src == king && candidate.dst.1 - candidate.src.1 == 2 * direction
+                })
+                .ok_or_else(|| SanError::Illegal(san.to_string()));
+        }
+
+        let (text, promotion) = match text.char_indices().last() {
+            Some((i, c)) if i > 0 && "QRBN".contains(c) => {
+                let promotion = PieceState::from_char(c);
+                (text[..i].trim_end_matches('='), promotion)
+            }
+            _ => (text, None),
+        };
+
+        let (state, text) = match text.chars().next() {
+            Some(c) if "KQRBN".contains(c) => {
+                (PieceState::from_char(c).ok_or_else(invalid)?, &text[1..])
+            }
+            Some(_) => (PieceState::Pawn, text),
+            None => return Err(invalid()),
+        };
+
+        if text.len() < 2 || !text.is_ascii() {
+            return Err(invalid());
+        }
+        let dst = square_to_point(&text[text.len() - 2..]).ok_or_else(invalid)?;
+        let qualifiers = &text[..text.len() - 2];
+        let capture = qualifiers.ends_with('x');
+        let disambiguation = qualifiers.trim_end_matches('x');
+
+        let mut file = None;
+        let mut rank = None;
+        for c in disambiguation.chars() {
+            match c {
+                'a'..='h' if file.is_none() && rank.is_none() => file = Some(c as i32 - 'a' as i32),
+                '1'..='8' if rank.is_none() => rank = Some(BOARD_LENGTH - (c as i32 - '0' as i32)),
+                _ => return Err(invalid()),
+            }
+        }
+        if state == PieceState::Pawn && capture != file.is_some() {
+            return Err(invalid());
+        }
+
+        let candidates: Vec<Move> = self
+            .legal_moves()
+            .into_iter()
+            .filter(|candidate| {
+                candidate.dst == dst
+                    && candidate.promotion == promotion
+                    && self.get_piece(candidate.src).map(|piece| piece.state) == Some(state)
+                    && file.is_none_or(|file| candidate.src.1 == file)
+                    && rank.is_none_or(|rank| candidate.src.0 == rank)
+                    && !(state == PieceState::King && (candidate.dst.1 - candidate.src.1).abs() > 1)
+            })
+            .collect();
+
+        match candidates[..] {
+            [] => Err(SanError::Illegal(san.to_string())),
+            [found] => Ok(found),
+            _ => Err(SanError::Ambiguous(san.to_string())),
+        }
+    }
+}
+
+impl Move {
+    pub fn to_san(self, gamestate: &GameState) -> String {
+        let mut san = self.san_move(gamestate);
+        let mut after = gamestate.clone();
+        after.make_move(self);
+        san.push_str(after.check_suffix());
+        san
+    }
+
+    pub(crate) fn san_move(self, gamestate: &GameState) -> String {
+        use PieceState::*;
+        let Some(piece) = gamestate.get_piece(self.src) else {
+            return String::new();
+        };
+
+        let mut san = String::new();
+        if piece.state == King && (self.dst.1 - self.src.1).abs() > 1 {
+            san.push_str(if self.dst.1 > self.src.1 {
+                "O-O"
+            } else {
+                "O-O-O"
+            });
+        } else {
+            let capture = !gamestate.is_empty(self.dst)
+                || (piece.state == Pawn && Some(self.dst) == gamestate.en_passant());
+            if piece.state == Pawn {
+                if capture {
+                    san.push_str(&point_to_square(self.src)[..1]);
+                }
+            } else {
+                san.push(piece.state.to_char());
+                san.push_str(&self.disambiguation(gamestate, piece));
+            }
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&point_to_square(self.dst));
+            if let Some(promotion) = self.promotion {
+                san.push('=');
+                san.push(promotion.to_char());
+            }
+        }
+        san
+    }
+
+    fn disambiguation(&self, gamestate: &GameState, piece: Piece) -> String {
+        let rivals: Vec<Point> = gamestate
+            .pieces(piece.color)
+            .iter()
+            .copied()
+            .filter(|point| {
+                *point != self.src
+                    && gamestate.get_piece(*point).map(|rival| rival.state) == Some(piece.state)
+                    && gamestate.piece_legal_moves(*point).contains(&self.dst)
+            })
+            .collect();
+
+        let square = point_to_square(self.src);
+        if rivals.is_empty() {
+            String::new()
+        } else if rivals.iter().all(|(_, y)| *y != self.src.1) {
+            square[..1].to_string()
+        } else if rivals.iter().all(|(x, _)| *x != self.src.0) {
+            square[1..].to_string()
+        } else {
+            square
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san(fen: &str, uci: &str) -> String {
+        let gamestate = GameState::from_fen(fen).expect("valid FEN");
+        let played = gamestate.parse_uci_move(uci).expect("legal test move");
+        played.to_san(&gamestate)
+    }
+
+    fn parse(fen: &str, san: &str) -> Result<String, SanError> {
+        let gamestate = GameState::from_fen(fen).expect("valid FEN");
+        gamestate.parse_san(san).map(|played| played.to_string())
+    }
+
+    #[test]
+    fn disambiguates_by_file_rank_or_square() {
+        let knights = "4k3/8/8/8/8/8/8/1N3N1K w - - 0 1";
+        assert_eq!(san(knights, "b1d2"), "Nbd2");
+        assert_eq!(san(knights, "f1d2"), "Nfd2");
+        assert_eq!(parse(knights, "Nbd2"), Ok("b1d2".to_string()));
+
+        let rooks = "4k3/8/8/R7/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(san(rooks, "a1a3"), "R1a3");
+        assert_eq!(san(rooks, "a5a3"), "R5a3");
+        assert_eq!(parse(rooks, "R5a3"), Ok("a5a3".to_string()));
+
+        let queens = "K7/8/k7/8/4Q2Q/8/8/7Q w - - 0 1";
+        assert_eq!(san(queens, "h4e1"), "Qh4e1");
+        assert_eq!(san(queens, "e4e1"), "Qee1");
+        assert_eq!(san(queens, "h1e1"), "Q1e1");
+        assert_eq!(parse(queens, "Qh4e1"), Ok("h4e1".to_string()));
+        assert_eq!(parse(queens, "Qee1"), Ok("e4e1".to_string()));
+        assert_eq!(parse(queens, "Q1e1"), Ok("h1e1".to_string()));
+    }
+
+    #[test]
+    fn writes_and_reads_promotions() {
+        let quiet = "3r4/4P3/8/8/8/5k2/8/K7 w - - 0 1";
+        assert_eq!(san(quiet, "e7e8q"), "e8=Q");
+        assert_eq!(san(quiet, "e7d8n"), "exd8=N");
+        assert_eq!(parse(quiet, "e8=Q"), Ok("e7e8q".to_string()));
+        assert_eq!(parse(quiet, "e8R"), Ok("e7e8r".to_string()));
+
+        let checking = "3r4/4P3/4k3/8/8/8/8/K7 w - - 0 1";
+        assert_eq!(san(checking, "e7d8n"), "exd8=N+");
+        assert_eq!(parse(checking, "exd8=N+"), Ok("e7d8n".to_string()));
+    }
+
+    #[test]
+    fn writes_and_reads_castling() {
+        let castling = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(san(castling, "e1g1"), "O-O");
+        assert_eq!(san(castling, "e1c1"), "O-O-O");
+        assert_eq!(parse(castling, "O-O"), Ok("e1g1".to_string()));
+        assert_eq!(parse(castling, "0-0-0"), Ok("e1c1".to_string()));
+    }
+
+    #[test]
+    fn marks_check_and_mate() {
+        let back_rank = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        assert_eq!(san(back_rank, "a1a8"), "Ra8#");
+        assert_eq!(san(back_rank, "a1a2"), "Ra2");
+        assert_eq!(san("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8+");
+        assert_eq!(parse(back_rank, "Ra8#"), Ok("a1a8".to_string()));
+    }
+
+    #[test]
+    fn reports_invalid_illegal_and_ambiguous_moves() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        for invalid in ["", "Zz9", "e", "Nz3", "xe4", "dxe", "Nb1c3d4"] {
+            assert_eq!(
+                parse(start, invalid),
+                Err(SanError::Invalid(invalid.to_string())),
+                "{invalid}"
+            );
+        }
+        for illegal in ["e5", "Ke2", "O-O", "Nd2"] {
+            assert_eq!(
+                parse(start, illegal),
+                Err(SanError::Illegal(illegal.to_string())),
+                "{illegal}"
+            );
+        }
+        assert_eq!(
+            parse("3r4/4P3/8/8/8/5k2/8/K7 w - - 0 1", "e8"),
+            Err(SanError::Illegal("e8".to_string()))
+        );
+        assert_eq!(
+            parse("K7/8/k7/8/4Q2Q/8/8/7Q w - - 0 1", "Qe1"),
+            Err(SanError::Ambiguous("Qe1".to_string()))
+        );
+        assert_eq!(
+            SanError::Ambiguous("Qe1".to_string()).to_string(),
+            "\"Qe1\" matches more than one legal move"
+        );
+    }
+}
@@ -0,0 +1,1127 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::bitboard::point_to_index;
+use crate::gamestate::GameState;
+use crate::util::*;
+
+pub const MAX_PIECES: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Option<Self> {
+        use Wdl::*;
+        Some(match value {
+            -2 => Loss,
+            -1 => BlessedLoss,
+            0 => Draw,
+            1 => CursedWin,
+            2 => Win,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TablebaseScore {
+    pub wdl: Wdl,
+    pub dtz: i32,
+}
+
+impl fmt::Display for TablebaseScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dtz = self.dtz.abs();
+        match self.wdl {
+            Wdl::Win => write!(f, "tablebase win, DTZ {dtz}"),
+            Wdl::CursedWin => write!(f, "tablebase win, DTZ {dtz}, drawn by the fifty-move rule"),
+            Wdl::Draw => write!(f, "tablebase draw"),
+            Wdl::BlessedLoss => {
+                write!(f, "tablebase loss, DTZ {dtz}, drawn by the fifty-move rule")
+            }
+            Wdl::Loss => write!(f, "tablebase loss, DTZ {dtz}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Wdl,
+    Dtz,
+}
+
+impl Kind {
+    fn extension(self) -> &'static str {
+        match self {
+            Kind::Wdl => "rtbw",
+            Kind::Dtz => "rtbz",
+        }
+    }
+
+    fn magic(self) -> [u8; 4] {
+        match self {
+            Kind::Wdl => WDL_MAGIC,
+            Kind::Dtz => DTZ_MAGIC,
+        }
+    }
+}
+
+type Tables = HashMap<(String, Kind), Option<Arc<Table>>>;
+
+enum TableProbe {
+    Value(i32),
+    OtherSide,
+}
+
+pub struct Tablebase {
+    directory: PathBuf,
+    available: HashSet<String>,
+    tables: Mutex<Tables>,
+}
+
+impl Tablebase {
+    pub fn open(directory: impl AsRef<Path>) -> io::Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        let mut available = HashSet::new();
+        for entry in fs::read_dir(&directory)? {
+            if let Some(name) = entry?.file_name().to_str() {
+                available.insert(name.to_string());
+            }
+        }
+        Ok(Self {
+            directory,
+            available,
+            tables: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn probe_wdl(&self, gamestate: &GameState) -> Option<Wdl> {
+        if !self.probeable(gamestate) {
+            return None;
+        }
+        let (wdl, _) = self.search(&mut gamestate.clone(), false)?;
+        Wdl::from_value(wdl)
+    }
+
+    pub fn probe_dtz(&self, gamestate: &GameState) -> Option<i32> {
+        if !self.probeable(gamestate) {
+            return None;
+        }
+        self.dtz(&mut gamestate.clone())
+    }
+
+    pub fn probe(&self, gamestate: &GameState) -> Option<TablebaseScore> {
+        Some(TablebaseScore {
+            wdl: self.probe_wdl(gamestate)?,
+            dtz: self.probe_dtz(gamestate)?,
+        })
+    }
+
+    fn probeable(&self, gamestate: &GameState) -> bool {
+        let count = gamestate.pieces(Color::White).len() + gamestate.pieces(Color::Black).len();
+        let castling = [Color::White, Color::Black]
+            .into_iter()
+            .any(|color| gamestate.can_castle(color, true) || gamestate.can_castle(color, false));
+        count <= MAX_PIECES && !castling
+    }
+
+    fn search(&self, gamestate: &mut GameState, zeroing_moves: bool) -> Option<(i32, bool)> {
+        let moves = gamestate.legal_moves();
+        let mut best = -2;
+        let mut searched = 0;
+        for legal_move in &moves {
+            let (capture, pawn) = move_kind(gamestate, legal_move);
+            if !(capture || zeroing_moves && pawn) {
+                continue;
+            }
+            searched += 1;
+
+            let undo = gamestate.make_move(*legal_move);
+            let value = self.search(gamestate, false);
+            gamestate.unmake_move(undo);
+            let value = -value?.0;
+
+            if value > best {
+                best = value;
+                if value >= 2 {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        let exhausted = searched > 0 && searched == moves.len();
+        let value = if exhausted {
+            best
+        } else {
+            match self.probe_table(gamestate, Kind::Wdl, 0)? {
+                TableProbe::Value(value) => value,
+                TableProbe::OtherSide => return None,
+            }
+        };
+
+        if best >= value {
+            Some((best, best > 0 || exhausted))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    fn dtz(&self, gamestate: &mut GameState) -> Option<i32> {
+        let (wdl, zeroing_best) = self.search(gamestate, true)?;
+        if wdl == 0 {
+            return Some(0);
+        }
+        if zeroing_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        match self.probe_table(gamestate, Kind::Dtz, wdl)? {
+            TableProbe::Value(dtz) => {
+                let cursed = if wdl.abs() == 1 { 100 } else { 0 };
+                Some((dtz + cursed) * wdl.signum())
+            }
+            TableProbe::OtherSide => {
+                let mut best = None;
+                for legal_move in gamestate.legal_moves() {
+                    let (capture, pawn) = move_kind(gamestate, &legal_move);
+                    let zeroing = capture || pawn;
+
+                    let undo = gamestate.make_move(legal_move);
+                    let dtz = if zeroing {
+                        self.search(gamestate, false)
+                            .map(|(wdl, _)| -dtz_before_zeroing(wdl))
+                    } else {
+                        self.dtz(gamestate).map(|dtz| -dtz)
+                    };
+                    let mates = gamestate.in_check() && gamestate.legal_moves().is_empty();
+                    gamestate.unmake_move(undo);
+
+                    let mut dtz = dtz?;
+                    if dtz == 1 && mates {
+                        best = Some(1);
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz.signum() == wdl.signum() && best.is_none_or(|best| dtz < best) {
+                        best = Some(dtz);
+                    }
+                }
+                Some(best.unwrap_or(-1))
+            }
+        }
+    }
+
+    fn probe_table(&self, gamestate: &GameState, kind: Kind, wdl: i32) -> Option<TableProbe> {
+        let mut pieces: Vec<(usize, u8)> = [Color::White, Color::Black]
+            .into_iter()
+            .flat_map(|color| gamestate.pieces(color))
+            .map(|point| {
+                let piece = gamestate
+                    .get_piece(*point)
+                    .expect("piece sets match the board");
+                (point_to_index(*point), piece_code(piece))
+            })
+            .collect();
+        if pieces.len() == 2 {
+            return Some(TableProbe::Value(0));
+        }
+        pieces.sort();
+
+        let white = side_name(gamestate, Color::White);
+        let black = side_name(gamestate, Color::Black);
+        let black_to_move = gamestate.players_turn() == Color::Black;
+        let (name, flip) = if white == black {
+            (format!("{white}v{black}"), black_to_move)
+        } else if self.has_table(&format!("{white}v{black}"), kind) {
+            (format!("{white}v{black}"), false)
+        } else {
+            (format!("{black}v{white}"), true)
+        };
+        let table = self.table(&name, kind)?;
+        table.probe(&pieces, flip, black_to_move, wdl)
+    }
+
+    fn has_table(&self, name: &str, kind: Kind) -> bool {
+        self.available
+            .contains(&format!("{name}.{}", kind.extension()))
+    }
+
+    fn table(&self, name: &str, kind: Kind) -> Option<Arc<Table>> {
+        let mut tables = self.tables.lock().expect("tablebase lock poisoned");
+        tables
+            .entry((name.to_string(), kind))
+            .or_insert_with(|| {
+                if !self.has_table(name, kind) {
+                    return None;
+                }
+                let path = self.directory.join(format!("{name}.{}", kind.extension()));
+                let bytes = fs::read(path).ok()?;
+                Table::parse(bytes, name, kind).map(Arc::new)
+            })
+            .clone()
+    }
+}
+
+fn move_kind(gamestate: &GameState, legal_move: &Move) -> (bool, bool) {
+    let pawn = gamestate
+        .get_piece(legal_move.src)
+        .is_some_and(|piece| piece.state == PieceState::Pawn);
+    let capture =
+        !gamestate.is_empty(legal_move.dst) || (pawn && legal_move.src.1 != legal_move.dst.1);
+    (capture, pawn)
+}
+
+fn dtz_before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        2 => 1,
+        1 => 101,
+        -1 => -101,
+        -2 => -1,
+        _ => 0,
+    }
+}
+
+fn piece_code(piece: Piece) -> u8 {
+    use PieceState::*;
+    let kind = match piece.state {
+        Pawn => 1,
+        Knight => 2,
+        Bishop => 3,
+        Rook => 4,
+        Queen => 5,
+        King => 6,
+    };
+    match piece.color {
+        Color::White => kind,
+        Color::Black => kind | 8,
+    }
+}
+
+fn side_name(gamestate: &GameState, color: Color) -> String {
+    let mut states: Vec<PieceState> = gamestate
+        .pieces(color)
+        .iter()
+        .filter_map(|point| gamestate.get_piece(*point))
+        .map(|piece| piece.state)
+        .collect();
+    states.sort_by_key(|state| *state as u8);
+    states.into_iter().map(PieceState::to_char).collect()
+}
+
+fn off_diagonal(square: usize) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+fn flip_diagonal(square: usize) -> usize {
+    ((square >> 3) | (square << 3)) & 63
+}
+
+struct Indices {
+    map_pawns: [usize; 64],
+    map_b1h1h7: [usize; 64],
+    map_a1d1d4: [usize; 64],
+    map_kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; MAX_PIECES],
+    lead_pawn_idx: [[u64; 64]; MAX_PIECES],
+    lead_pawns_size: [[u64; 4]; MAX_PIECES],
+}
+
+fn indices() -> &'static Indices {
+    static INDICES: OnceLock<Indices> = OnceLock::new();
+    INDICES.get_or_init(|| {
+        let mut indices = Indices {
+            map_pawns: [0; 64],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; MAX_PIECES],
+            lead_pawn_idx: [[0; 64]; MAX_PIECES],
+            lead_pawns_size: [[0; 4]; MAX_PIECES],
+        };
+
+        let mut code = 0;
+        for square in 0..64 {
+            if off_diagonal(square) < 0 {
+                indices.map_b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        let triangle = (0..64).filter(|square| square % 8 <= 3 && square / 8 <= 3);
+        let mut code = 0;
+        for square in triangle.clone().filter(|&square| off_diagonal(square) < 0) {
+            indices.map_a1d1d4[square] = code;
+            code += 1;
+        }
+        for square in triangle.clone().filter(|&square| off_diagonal(square) == 0) {
+            indices.map_a1d1d4[square] = code;
+            code += 1;
+        }
+
+        let adjacent =
+            |a: usize, b: usize| (a / 8).abs_diff(b / 8) <= 1 && (a % 8).abs_diff(b % 8) <= 1;
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for idx in 0..10 {
+            let Some(first) = triangle
+                .clone()
+                .find(|&square| indices.map_a1d1d4[square] == idx && off_diagonal(square) <= 0)
+            else {
+                continue;
+            };
+            for second in 0..64 {
+                if adjacent(first, second) {
+                    continue;
+                }
+                if off_diagonal(first) == 0 && off_diagonal(second) > 0 {
+                    continue;
+                }
+                if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                    both_on_diagonal.push((idx, second));
+                } else {
+                    indices.map_kk[idx][second] = code;
+                    code += 1;
+                }
+            }
+        }
+        for (idx, second) in both_on_diagonal {
+            indices.map_kk[idx][second] = code;
+            code += 1;
+        }
+
+        indices.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                let with = if k > 0 {
+                    indices.binomial[k - 1][n - 1]
+                } else {
+                    0
+                };
+                let without = if k < n { indices.binomial[k][n - 1] } else { 0 };
+                indices.binomial[k][n] = with + without;
+            }
+        }
+
+        let mut placed = 0;
+        for lead_pawns in 1..MAX_PIECES - 1 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        indices.map_pawns[square] = 47 - placed;
+                        indices.map_pawns[square ^ 7] = 46 - placed;
+                        placed += 2;
+                    }
+                    indices.lead_pawn_idx[lead_pawns][square] = idx;
+                    idx += indices.binomial[lead_pawns - 1][indices.map_pawns[square]];
+                }
+                indices.lead_pawns_size[lead_pawns][file] = idx;
+            }
+        }
+        indices
+    })
+}
+
+#[derive(Debug, Clone, Default)]
+struct PairsData {
+    flags: u8,
+    block_size: usize,
+    span: u64,
+    num_blocks: usize,
+    min_sym_len: u32,
+    lowest_sym: usize,
+    base64: Vec<u64>,
+    symlen: Vec<u8>,
+    btree: usize,
+    block_length: usize,
+    block_length_size: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    data: usize,
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    group_len: [usize; MAX_PIECES + 1],
+    map_idx: [usize; 4],
+}
+
+struct Table {
+    bytes: Vec<u8>,
+    kind: Kind,
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    symmetric: bool,
+    pawn_count: [usize; 2],
+    map: usize,
+    pairs: Vec<Vec<PairsData>>,
+}
+
+impl Table {
+    fn parse(bytes: Vec<u8>, name: &str, kind: Kind) -> Option<Self> {
+        if bytes.len() % 64 != 16 || bytes.get(..4)? != kind.magic() {
+            return None;
+        }
+        let (white, black) = name.split_once('v')?;
+        let count = |side: &str, letter: char| side.chars().filter(|&c| c == letter).count();
+        let white_pawns = count(white, 'P');
+        let black_pawns = count(black, 'P');
+        let lead_white = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let mut table = Self {
+            kind,
+            piece_count: white.len() + black.len(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces: "QRBNP"
+                .chars()
+                .any(|letter| count(white, letter) == 1 || count(black, letter) == 1),
+            symmetric: white == black,
+            pawn_count: if lead_white {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+            map: 0,
+            pairs: Vec::new(),
+            bytes,
+        };
+        if table.piece_count > MAX_PIECES {
+            return None;
+        }
+        table.read_header()?;
+        Some(table)
+    }
+
+    fn sides(&self) -> usize {
+        if self.kind == Kind::Wdl && !self.symmetric {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn files(&self) -> usize {
+        if self.has_pawns {
+            4
+        } else {
+            1
+        }
+    }
+
+    fn byte(&self, offset: usize) -> Option<u8> {
+        self.bytes.get(offset).copied()
+    }
+
+    fn u16_le(&self, offset: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(
+            self.bytes.get(offset..offset + 2)?.try_into().ok()?,
+        ))
+    }
+
+    fn u32_le(&self, offset: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(
+            self.bytes.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    }
+
+    fn u32_be(&self, offset: usize) -> Option<u32> {
+        Some(u32::from_be_bytes(
+            self.bytes.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    }
+
+    fn u64_be(&self, offset: usize) -> Option<u64> {
+        Some(u64::from_be_bytes(
+            self.bytes.get(offset..offset + 8)?.try_into().ok()?,
+        ))
+    }
+
+    fn read_header(&mut self) -> Option<()> {
+        let flags = self.byte(4)?;
+        if (flags & 2 != 0) != self.has_pawns || (flags & 1 != 0) == self.symmetric {
+            return None;
+        }
+        let mut offset = 5;
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+
+        self.pairs = vec![vec![PairsData::default(); self.sides()]; self.files()];
+        for file in 0..self.files() {
+            let first = self.byte(offset)?;
+            let second = if both_pawns {
+                self.byte(offset + 1)?
+            } else {
+                0xff
+            };
+            let order = [
+                [(first & 0xf) as usize, (second & 0xf) as usize],
+                [(first >> 4) as usize, (second >> 4) as usize],
+            ];
+            offset += 1 + both_pawns as usize;
+
+            for k in 0..self.piece_count {
+                let byte = self.byte(offset)?;
+                for side in 0..self.sides() {
+                    self.pairs[file][side].pieces[k] =
+                        if side == 1 { byte >> 4 } else { byte & 0xf };
+                }
+                offset += 1;
+            }
+            for (side, order) in order.into_iter().enumerate().take(self.sides()) {
+                self.set_groups(file, side, order);
+            }
+        }
+        offset += offset & 1;
+
+        for file in 0..self.files() {
+            for side in 0..self.sides() {
+                offset = self.set_sizes(file, side, offset)?;
+            }
+        }
+
+        if self.kind == Kind::Dtz {
+            self.map = offset;
+            for file in 0..self.files() {
+                let flags = self.pairs[file][0].flags;
+                if flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if flags & FLAG_WIDE != 0 {
+                    offset += offset & 1;
+                    for i in 0..4 {
+                        self.pairs[file][0].map_idx[i] = (offset - self.map) / 2 + 1;
+                        offset += 2 * self.u16_le(offset)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        self.pairs[file][0].map_idx[i] = offset - self.map + 1;
+                        offset += self.byte(offset)? as usize + 1;
+                    }
+                }
+            }
+            offset += offset & 1;
+        }
+
+        for file in 0..self.files() {
+            for side in 0..self.sides() {
+                let pairs = &mut self.pairs[file][side];
+                pairs.sparse_index = offset;
+                offset += pairs.sparse_index_size * 6;
+            }
+        }
+        for file in 0..self.files() {
+            for side in 0..self.sides() {
+                let pairs = &mut self.pairs[file][side];
+                pairs.block_length = offset;
+                offset += pairs.block_length_size * 2;
+            }
+        }
+        for file in 0..self.files() {
+            for side in 0..self.sides() {
+                offset = (offset + 0x3f) & !0x3f;
+                let pairs = &mut self.pairs[file][side];
+                pairs.data = offset;
+                offset += pairs.num_blocks * pairs.block_size;
+            }
+        }
+        Some(())
+    }
+
+    fn set_groups(&mut self, file: usize, side: usize, order: [usize; 2]) {
+        let indices = indices();
+        let mut first_len: i32 = if self.has_pawns {
+            0
+        } else if self.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let has_pawns = self.has_pawns;
+        let has_unique_pieces = self.has_unique_pieces;
+        let piece_count = self.piece_count;
+        let pairs = &mut self.pairs[file][side];
+
+        let mut n = 0;
+        pairs.group_len[0] = 1;
+        for i in 1..piece_count {
+            first_len -= 1;
+            if first_len > 0 || pairs.pieces[i] == pairs.pieces[i - 1] {
+                pairs.group_len[n] += 1;
+            } else {
+                n += 1;
+                pairs.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        pairs.group_len[n] = 0;
+
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares =
+            64 - pairs.group_len[0] - if both_pawns { pairs.group_len[1] } else { 0 };
+        let mut idx = 1;
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                pairs.group_idx[0] = idx;
+                idx *= if has_pawns {
+                    indices.lead_pawns_size[pairs.group_len[0]][file]
+                } else if has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                pairs.group_idx[1] = idx;
+                idx *= indices.binomial[pairs.group_len[1]][48 - pairs.group_len[0]];
+            } else {
+                pairs.group_idx[next] = idx;
+                idx *= indices.binomial[pairs.group_len[next]][free_squares];
+                free_squares -= pairs.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        pairs.group_idx[n] = idx;
+    }
+
+    fn set_sizes(&mut self, file: usize, side: usize, mut offset: usize) -> Option<usize> {
+        let mut pairs = std::mem::take(&mut self.pairs[file][side]);
+        pairs.flags = self.byte(offset)?;
+        offset += 1;
+
+        if pairs.flags & FLAG_SINGLE_VALUE != 0 {
+            pairs.min_sym_len = self.byte(offset)? as u32;
+            self.pairs[file][side] = pairs;
+            return Some(offset + 1);
+        }
+
+        let groups = pairs.group_len.iter().position(|&len| len == 0)?;
+        let size = pairs.group_idx[groups];
+        pairs.block_size = 1 << self.byte(offset)?;
+        pairs.span = 1 << self.byte(offset + 1)?;
+        pairs.sparse_index_size = size.div_ceil(pairs.span) as usize;
+        let padding = self.byte(offset + 2)? as usize;
+        pairs.num_blocks = self.u32_le(offset + 3)? as usize;
+        pairs.block_length_size = pairs.num_blocks + padding;
+        let max_sym_len = self.byte(offset + 7)? as u32;
+        if max_sym_len >= 64 {
+            return None;
+        }
+        pairs.min_sym_len = self.byte(offset + 8)? as u32;
+        offset += 9;
+        pairs.lowest_sym = offset;
+
+        let lengths = (max_sym_len + 1).checked_sub(pairs.min_sym_len)? as usize;
+        pairs.base64 = vec![0; lengths];
+        for i in (0..lengths.saturating_sub(1)).rev() {
+            let lowest = self.u16_le(pairs.lowest_sym + 2 * i)? as u64;
+            let next_lowest = self.u16_le(pairs.lowest_sym + 2 * (i + 1))? as u64;
+            pairs.base64[i] = pairs.base64[i + 1]
+                .wrapping_add(lowest)
+                .wrapping_sub(next_lowest)
+                / 2;
+        }
+        for (i, base) in pairs.base64.iter_mut().enumerate() {
+            *base = 64u32
+                .checked_sub(i as u32 + pairs.min_sym_len)
+                .and_then(|shift| base.checked_shl(shift))
+                .unwrap_or(0);
+        }
+        offset += lengths * 2;
+
+        let symbols = self.u16_le(offset)? as usize;
+        offset += 2;
+        pairs.btree = offset;
+        pairs.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                pairs.symlen[symbol] = self.set_symlen(&mut pairs, symbol, &mut visited)?;
+            }
+        }
+        self.pairs[file][side] = pairs;
+        Some(offset + symbols * 3 + (symbols & 1))
+    }
+
+    fn set_symlen(&self, pairs: &mut PairsData, symbol: usize, visited: &mut [bool]) -> Option<u8> {
+        visited[symbol] = true;
+        let (left, right) = self.children(pairs, symbol)?;
+        if right == 0xfff {
+            return Some(0);
+        }
+        for child in [left, right] {
+            if !*visited.get(child)? {
+                pairs.symlen[child] = self.set_symlen(pairs, child, visited)?;
+            }
+        }
+        Some(
+            pairs.symlen[left]
+                .wrapping_add(pairs.symlen[right])
+                .wrapping_add(1),
+        )
+    }
+
+    fn children(&self, pairs: &PairsData, symbol: usize) -> Option<(usize, usize)> {
+        let offset = pairs.btree + 3 * symbol;
+        let (a, b, c) = (
+            self.byte(offset)?,
+            self.byte(offset + 1)?,
+            self.byte(offset + 2)?,
+        );
+        let left = ((b as usize & 0xf) << 8) | a as usize;
+        let right = ((c as usize) << 4) | (b as usize >> 4);
+        Some((left, right))
+    }
+
+    fn decompress(&self, pairs: &PairsData, idx: u64) -> Option<i32> {
+        if pairs.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(pairs.min_sym_len as i32);
+        }
+
+        let k = (idx / pairs.span) as usize;
+        let mut block = self.u32_le(pairs.sparse_index + 6 * k)? as usize;
+        let mut offset = self.u16_le(pairs.sparse_index + 6 * k + 4)? as i64;
+        offset += (idx % pairs.span) as i64 - (pairs.span / 2) as i64;
+
+        let block_length = |block: usize| -> Option<i64> {
+            (block < pairs.block_length_size).then_some(())?;
+            Some(self.u16_le(pairs.block_length + 2 * block)? as i64)
+        };
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        let mut position = pairs.data + block * pairs.block_size;
+        let mut buffer = self.u64_be(position)?;
+        position += 8;
+        let mut buffer_size = 64;
+        let mut symbol;
+        loop {
+            let mut len = 0;
+            while buffer < *pairs.base64.get(len)? {
+                len += 1;
+            }
+            symbol =
+                ((buffer - pairs.base64[len]) >> (64 - len as u32 - pairs.min_sym_len)) as usize;
+            symbol += self.u16_le(pairs.lowest_sym + 2 * len)? as usize;
+
+            let symlen = *pairs.symlen.get(symbol)? as i64;
+            if offset < symlen + 1 {
+                break;
+            }
+            offset -= symlen + 1;
+            let len = len as u32 + pairs.min_sym_len;
+            buffer <<= len;
+            buffer_size -= len;
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= (self.u32_be(position)? as u64) << (64 - buffer_size);
+                position += 4;
+            }
+        }
+
+        while pairs.symlen[symbol] != 0 {
+            let (left, right) = self.children(pairs, symbol)?;
+            let left_len = *pairs.symlen.get(left)? as i64;
+            if offset < left_len + 1 {
+                symbol = left;
+            } else {
+                offset -= left_len + 1;
+                symbol = right;
+            }
+        }
+        Some(self.children(pairs, symbol)?.0 as i32)
+    }
+
+    fn probe(
+        &self,
+        position: &[(usize, u8)],
+        flip: bool,
+        black_to_move: bool,
+        wdl: i32,
+    ) -> Option<TableProbe> {
+        let indices = indices();
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let side = (flip != black_to_move) as usize;
+
+        let mut squares = Vec::with_capacity(position.len());
+        let mut pieces = Vec::with_capacity(position.len());
+        let lead_pawn = self
+            .has_pawns
+            .then(|| self.pairs[0][0].pieces[0] ^ flip_color);
+        let mut file = 0;
+        if self.has_pawns {
+            for &(square, piece) in position
+                .iter()
+                .filter(|(_, piece)| Some(*piece) == lead_pawn)
+            {
+                squares.push(square ^ flip_squares);
+                pieces.push(piece ^ flip_color);
+            }
+            let lead = (0..squares.len()).max_by_key(|&i| indices.map_pawns[squares[i]])?;
+            squares.swap(0, lead);
+            file = (squares[0] % 8).min(7 - squares[0] % 8);
+        }
+        let lead_pawns = squares.len();
+
+        if self.kind == Kind::Dtz {
+            let flags = self.pairs[file][0].flags;
+            let shared = self.symmetric && !self.has_pawns;
+            if (flags & FLAG_STM) as usize != side && !shared {
+                return Some(TableProbe::OtherSide);
+            }
+        }
+
+        for &(square, piece) in position {
+            if Some(piece) == lead_pawn {
+                continue;
+            }
+            squares.push(square ^ flip_squares);
+            pieces.push(piece ^ flip_color);
+        }
+
+        let pairs = &self.pairs[file][side % self.sides()];
+        for i in lead_pawns..squares.len().saturating_sub(1) {
+            if let Some(j) = (i + 1..squares.len()).find(|&j| pairs.pieces[i] == pieces[j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        if squares[0] % 8 > 3 {
+            squares.iter_mut().for_each(|square| *square ^= 7);
+        }
+
+        let mut idx;
+        if self.has_pawns {
+            idx = indices.lead_pawn_idx[lead_pawns][squares[0]];
+            squares[1..lead_pawns].sort_by_key(|&square| indices.map_pawns[square]);
+            for (i, &square) in squares.iter().enumerate().take(lead_pawns).skip(1) {
+                idx += indices.binomial[i][indices.map_pawns[square]];
+            }
+        } else {
+            if squares[0] / 8 > 3 {
+                squares.iter_mut().for_each(|square| *square ^= 56);
+            }
+            for i in 0..pairs.group_len[0] {
+                match off_diagonal(squares[i]) {
+                    0 => continue,
+                    off if off > 0 => squares[i..]
+                        .iter_mut()
+                        .for_each(|square| *square = flip_diagonal(*square)),
+                    _ => {}
+                }
+                break;
+            }
+
+            idx = if self.has_unique_pieces {
+                let rank = |square: usize| (square / 8) as u64;
+                let adjust1 = (squares[1] > squares[0]) as usize;
+                let adjust2 =
+                    (squares[2] > squares[0]) as usize + (squares[2] > squares[1]) as usize;
+                if off_diagonal(squares[0]) != 0 {
+                    (indices.map_a1d1d4[squares[0]] as u64 * 63 + (squares[1] - adjust1) as u64)
+                        * 62
+                        + (squares[2] - adjust2) as u64
+                } else if off_diagonal(squares[1]) != 0 {
+                    (6 * 63 + rank(squares[0]) * 28 + indices.map_b1h1h7[squares[1]] as u64) * 62
+                        + (squares[2] - adjust2) as u64
+                } else if off_diagonal(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(squares[0]) * 7 * 28
+                        + (rank(squares[1]) - adjust1 as u64) * 28
+                        + indices.map_b1h1h7[squares[2]] as u64
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(squares[0]) * 7 * 6
+                        + (rank(squares[1]) - adjust1 as u64) * 6
+                        + (rank(squares[2]) - adjust2 as u64)
+                }
+            } else {
+                indices.map_kk[indices.map_a1d1d4[squares[0]]][squares[1]]
+            };
+        }
+
+        idx *= pairs.group_idx[0];
+        let mut start = pairs.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        for next in 1..=MAX_PIECES {
+            let len = pairs.group_len[next];
+            if len == 0 {
+                break;
+            }
+            squares[start..start + len].sort();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let adjust = squares[..start]
+                    .iter()
+                    .filter(|&&other| square > other)
+                    .count();
+                let square = square.checked_sub(adjust + 8 * remaining_pawns as usize)?;
+                n += indices.binomial[i + 1][square];
+            }
+            remaining_pawns = false;
+            idx += n * pairs.group_idx[next];
+            start += len;
+        }
+
+        let value = self.decompress(pairs, idx)?;
+        Some(TableProbe::Value(self.map_score(file, value, wdl)?))
+    }
+
+    fn map_score(&self, file: usize, value: i32, wdl: i32) -> Option<i32> {
+        if self.kind == Kind::Wdl {
+            return Some(value - 2);
+        }
+        let pairs = &self.pairs[file][0];
+        let mut value = value;
+        if pairs.flags & FLAG_MAPPED != 0 {
+            let map_idx = pairs.map_idx[[1, 3, 0, 2, 0][(wdl + 2) as usize]];
+            value = if pairs.flags & FLAG_WIDE != 0 {
+                self.u16_le(self.map + 2 * (map_idx + value as usize))? as i32
+            } else {
+                self.byte(self.map + map_idx + value as usize)? as i32
+            };
+        }
+        if (wdl == 2 && pairs.flags & FLAG_WIN_PLIES == 0)
+            || (wdl == -2 && pairs.flags & FLAG_LOSS_PLIES == 0)
+            || wdl.abs() == 1
+        {
+            value *= 2;
+        }
+        Some(value + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("chess-syzygy-{name}-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn index_tables_cover_every_placement() {
+        let indices = indices();
+        let mut kings: Vec<u64> = (0..10)
+            .flat_map(|idx| (0..64).map(move |square| (idx, square)))
+            .filter(|&(idx, square)| indices.map_kk[idx][square] != 0 || (idx, square) == (0, 0))
+            .map(|(idx, square)| indices.map_kk[idx][square])
+            .collect();
+        kings.sort();
+        kings.dedup();
+        assert_eq!(kings.len(), 462);
+        assert_eq!(kings.last(), Some(&461));
+
+        assert_eq!(indices.binomial[2][5], 10);
+        assert_eq!(indices.lead_pawns_size[1], [6; 4]);
+        assert_eq!(indices.map_pawns[8], 47);
+        assert_eq!(indices.map_pawns[15], 46);
+    }
+
+    #[test]
+    fn bare_kings_are_drawn_without_tables() {
+        let directory = scratch_directory("empty");
+        let tablebase = Tablebase::open(&directory).unwrap();
+        let kings = GameState::from_fen("8/8/4k3/8/8/3K4/8/8 w - - 0 1").unwrap();
+        assert_eq!(tablebase.probe_wdl(&kings), Some(Wdl::Draw));
+        assert_eq!(tablebase.probe_dtz(&kings), Some(0));
+        assert_eq!(
+            tablebase.probe(&kings).unwrap().to_string(),
+            "tablebase draw"
+        );
+
+        let missing = GameState::from_fen("8/8/4k3/8/8/3K4/8/7Q w - - 0 1").unwrap();
+        assert_eq!(tablebase.probe_wdl(&missing), None);
+        assert_eq!(tablebase.probe_wdl(&GameState::new()), None);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn reads_single_value_tables() {
+        let mut bytes = WDL_MAGIC.to_vec();
+        bytes.push(1);
+        bytes.push(0x00);
+        bytes.extend([0x66, 0xee, 0x22]);
+        bytes.push(0);
+        bytes.extend([FLAG_SINGLE_VALUE, 2, FLAG_SINGLE_VALUE, 2]);
+        bytes.resize(16, 0);
+        let directory = scratch_directory("single");
+        fs::write(directory.join("KNvK.rtbw"), &bytes).unwrap();
+        let tablebase = Tablebase::open(&directory).unwrap();
+
+        for fen in [
+            "8/8/4k3/8/8/3K4/8/6N1 w - - 0 1",
+            "8/8/4K3/8/8/3k4/8/6n1 b - - 0 1",
+        ] {
+            let gamestate = GameState::from_fen(fen).unwrap();
+            assert_eq!(tablebase.probe_wdl(&gamestate), Some(Wdl::Draw));
+        }
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn probes_three_piece_tables() {
+        let tablebase =
+            Tablebase::open(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy")).unwrap();
+        let probe = |fen: &str| {
+            let score = tablebase.probe(&GameState::from_fen(fen).unwrap()).unwrap();
+            (score.wdl, score.dtz)
+        };
+
+        let mate_in_one = tablebase
+            .probe(&GameState::from_fen("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1").unwrap())
+            .unwrap();
+        assert_eq!((mate_in_one.wdl, mate_in_one.dtz), (Wdl::Win, 1));
+        assert_eq!(mate_in_one.to_string(), "tablebase win, DTZ 1");
+
+        assert_eq!(probe("8/8/8/5k2/8/8/1Q6/K7 w - - 0 1"), (Wdl::Win, 19));
+        assert_eq!(probe("8/8/8/8/8/3k4/8/3K1Q2 b - - 0 1"), (Wdl::Loss, -16));
+        assert_eq!(probe("8/8/8/8/8/3k4/2R5/K7 w - - 0 1"), (Wdl::Win, 31));
+        assert_eq!(probe("k7/2r5/3K4/8/8/8/8/8 b - - 0 1"), (Wdl::Win, 31));
+        assert_eq!(probe("8/8/8/8/8/8/1Rk5/K7 b - - 0 1"), (Wdl::Loss, -32));
+        assert_eq!(probe("8/8/8/8/8/3k4/3R4/7K b - - 0 1"), (Wdl::Draw, 0));
+
+        assert_eq!(probe("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), (Wdl::Win, 3));
+        assert_eq!(probe("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), (Wdl::Loss, -4));
+        assert_eq!(probe("8/3P4/8/8/8/8/k7/7K w - - 0 1"), (Wdl::Win, 1));
+        assert_eq!(probe("8/8/8/8/8/3k4/3P4/3K4 w - - 0 1"), (Wdl::Draw, 0));
+        assert_eq!(probe("8/8/8/8/8/2K5/3P4/1k6 w - - 0 1"), (Wdl::Win, 1));
+        assert_eq!(probe("8/8/8/8/8/8/8/2KB2k1 w - - 0 1"), (Wdl::Draw, 0));
+    }
+}
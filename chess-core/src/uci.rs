@@ -0,0 +1,507 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::engine::{Engine, Score, SearchInfo, SearchLimits};
+use crate::gamestate::GameState;
+use crate::util::*;
+
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+impl GameState {
+    pub fn parse_uci_move(&self, text: &str) -> Option<Move> {
+        self.legal_moves()
+            .into_iter()
+            .find(|legal_move| legal_move.to_string() == text)
+    }
+}
+
+#[derive(Debug)]
+pub enum UciError {
+    Io(io::Error),
+    EngineExited,
+    IllegalMove(String),
+}
+
+impl fmt::Display for UciError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use UciError::*;
+        match self {
+            Io(e) => write!(f, "could not talk to the engine: {e}"),
+            EngineExited => write!(f, "the engine exited unexpectedly"),
+            IllegalMove(text) => write!(f, "the engine played an illegal move \"{text}\""),
+        }
+    }
+}
+
+impl Error for UciError {}
+
+impl From<io::Error> for UciError {
+    fn from(e: io::Error) -> Self {
+        UciError::Io(e)
+    }
+}
+
+pub struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    name: Option<String>,
+}
+
+impl UciEngine {
+    pub fn spawn(path: impl AsRef<Path>) -> Result<Self, UciError> {
+        let mut child = Command::new(path.as_ref())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let mut engine = Self {
+            child,
+            stdin,
+            stdout,
+            name: None,
+        };
+
+        engine.send("uci")?;
+        loop {
+            let line = engine.read_line()?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = Some(name.trim().to_string());
+            } else if line.trim() == "uciok" {
+                break;
+            }
+        }
+        engine.wait_ready()?;
+        Ok(engine)
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn new_game(&mut self) -> Result<(), UciError> {
+        self.send("ucinewgame")?;
+        self.wait_ready()
+    }
+
+    pub fn best_move(
+        &mut self,
+        gamestate: &GameState,
+        movetime: Duration,
+    ) -> Result<Move, UciError> {
+        self.send(&position_command(gamestate))?;
+        self.send(&format!("go movetime {}", movetime.as_millis()))?;
+        loop {
+            let line = self.read_line()?;
+            let mut tokens = line.split_whitespace();
+            if tokens.next() != Some("bestmove") {
+                continue;
+            }
+            let text = tokens.next().unwrap_or_default();
+            return gamestate
+                .parse_uci_move(text)
+                .ok_or_else(|| UciError::IllegalMove(text.to_string()));
+        }
+    }
+
+    fn wait_ready(&mut self) -> Result<(), UciError> {
+        self.send("isready")?;
+        while self.read_line()?.trim() != "readyok" {}
+        Ok(())
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), UciError> {
+        writeln!(self.stdin, "{command}")?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> Result<String, UciError> {
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(UciError::EngineExited);
+        }
+        Ok(line)
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        if self.send("quit").is_err() || self.child.try_wait().is_err() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+pub fn position_command(gamestate: &GameState) -> String {
+    let mut command = match gamestate.starting_fen() {
+        Some(fen) => format!("position fen {fen}"),
+        None => "position startpos".to_string(),
+    };
+    if !gamestate.history().is_empty() {
+        command.push_str(" moves");
+        for played in gamestate.history() {
+            command.push(' ');
+            command.push_str(&played.played.to_string());
+        }
+    }
+    command
+}
+
+pub fn run_uci<R: BufRead, W: Write + Send + 'static>(input: R, output: W) -> io::Result<()> {
+    let output = Arc::new(Mutex::new(output));
+    let mut gamestate = GameState::new();
+    let engine = Engine::new();
+    let stop = engine.stop_handle();
+    let mut engine = Some(engine);
+    let mut search: Option<JoinHandle<Engine>> = None;
+    let mut infinite = false;
+
+    let finish_search = |engine: &mut Option<Engine>, search: &mut Option<JoinHandle<Engine>>| {
+        if let Some(handle) = search.take() {
+            stop.store(true, Ordering::Relaxed);
+            *engine = handle.join().ok();
+        }
+        stop.store(false, Ordering::Relaxed);
+    };
+
+    for line in input.lines() {
+        let line = line?;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("uci") => send(
+                &output,
+                &format!(
+                    "id name chess {}\nid author the chess authors\nuciok",
+                    env!("CARGO_PKG_VERSION")
+                ),
+            )?,
+            Some("isready") => send(&output, "readyok")?,
+            Some("ucinewgame") => {
+                finish_search(&mut engine, &mut search);
+                if let Some(ref mut engine) = engine {
+                    engine.clear();
+                }
+                gamestate = GameState::new();
+            }
+            Some("position") => {
+                finish_search(&mut engine, &mut search);
+                if let Err(error) = parse_position(&mut gamestate, tokens) {
+                    send(&output, &format!("info string {error}"))?;
+                }
+            }
+            Some("go") => {
+                finish_search(&mut engine, &mut search);
+                let limits;
+                (limits, infinite) = parse_go(tokens, gamestate.players_turn());
+                let Some(mut searcher) = engine.take() else {
+                    continue;
+                };
+                let gamestate = gamestate.clone();
+                let output = Arc::clone(&output);
+                let stop = Arc::clone(&stop);
+                search = Some(thread::spawn(move || {
+                    let best = searcher.search(&gamestate, limits, |info| {
+                        let _ = send(&output, &info_line(info));
+                    });
+                    while infinite && !stop.load(Ordering::Relaxed) {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    let best = best.map_or("0000".to_string(), |best| best.to_string());
+                    let _ = send(&output, &format!("bestmove {best}"));
+                    searcher
+                }));
+            }
+            Some("stop") => finish_search(&mut engine, &mut search),
+            Some("quit") => {
+                stop.store(true, Ordering::Relaxed);
+                break;
+            }
+            _ => {}
+        }
+    }
+    if let Some(handle) = search {
+        if infinite {
+            stop.store(true, Ordering::Relaxed);
+        }
+        let _ = handle.join();
+    }
+    Ok(())
+}
+
+fn send<W: Write>(output: &Mutex<W>, message: &str) -> io::Result<()> {
+    let mut output = output.lock().expect("output lock poisoned");
+    writeln!(output, "{message}")?;
+    output.flush()
+}
+
+/// Sets `gamestate` from a `position` command. On an error the position is
+/// never left as it was: an invalid base falls back to the start position,
+/// and the moves before an illegal one are still played.
+fn parse_position<'a>(
+    gamestate: &mut GameState,
+    mut tokens: impl Iterator<Item = &'a str>,
+) -> Result<(), String> {
+    *gamestate = GameState::new();
+    match tokens.next() {
+        Some("startpos") => match tokens.next() {
+            Some("moves") | None => {}
+            Some(token) => return Err(format!("unexpected \"{token}\" after startpos")),
+        },
+        Some("fen") => {
+            let fen: Vec<&str> = tokens
+                .by_ref()
+                .take_while(|token| *token != "moves")
+                .collect();
+            *gamestate = GameState::from_fen(&fen.join(" ")).map_err(|e| e.to_string())?;
+        }
+        _ => return Err("position must be followed by startpos or fen".to_string()),
+    }
+    for text in tokens {
+        let legal_move = gamestate
+            .parse_uci_move(text)
+            .ok_or_else(|| format!("illegal move \"{text}\""))?;
+        gamestate.move_piece(legal_move);
+    }
+    Ok(())
+}
+
+fn parse_go<'a>(mut tokens: impl Iterator<Item = &'a str>, side: Color) -> (SearchLimits, bool) {
+    let mut limits = SearchLimits::default();
+    let mut infinite = false;
+    let mut remaining = None;
+    let mut increment = Duration::ZERO;
+    let mut moves_to_go = None;
+    while let Some(token) = tokens.next() {
+        let mut value = || tokens.next().and_then(|value| value.parse::<u64>().ok());
+        let millis = |value: Option<u64>| value.map(Duration::from_millis);
+        match (token, side) {
+            ("depth", _) => limits.depth = value().map(|depth| depth as u32),
+            ("movetime", _) => limits.movetime = millis(value()),
+            ("wtime", Color::White) | ("btime", Color::Black) => remaining = millis(value()),
+            ("winc", Color::White) | ("binc", Color::Black) => {
+                increment = millis(value()).unwrap_or_default()
+            }
+            ("wtime" | "btime" | "winc" | "binc", _) => {
+                value();
+            }
+            ("movestogo", _) => moves_to_go = value().map(|moves| moves as u32),
+            ("infinite", _) => infinite = true,
+            _ => {}
+        }
+    }
+    if limits.movetime.is_none() && !infinite {
+        if let Some(remaining) = remaining {
+            limits.movetime = Some(allocate_time(remaining, increment, moves_to_go));
+        }
+    }
+    (limits, infinite)
+}
+
+fn allocate_time(remaining: Duration, increment: Duration, moves_to_go: Option<u32>) -> Duration {
+    let moves = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+    let budget = remaining / moves + increment * 3 / 4;
+    budget
+        .min(remaining.saturating_sub(MOVE_OVERHEAD))
+        .max(Duration::from_millis(1))
+}
+
+fn info_line(info: &SearchInfo) -> String {
+    let score = match info.score {
+        Score::Centipawns(centipawns) => format!("cp {centipawns}"),
+        Score::Mate(moves) => format!("mate {moves}"),
+    };
+    let millis = info.time.as_millis().max(1);
+    let pv = info
+        .pv
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "info depth {} score {score} nodes {} nps {} time {} pv {pv}",
+        info.depth,
+        info.nodes,
+        info.nodes as u128 * 1000 / millis,
+        info.time.as_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn session(commands: &str) -> Vec<String> {
+        let buffer = SharedBuffer::default();
+        run_uci(commands.as_bytes(), buffer.clone()).unwrap();
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        output.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn handshake() {
+        let lines = session("uci\nisready\nquit\n");
+        assert!(lines[0].starts_with("id name"));
+        assert!(lines.contains(&"uciok".to_string()));
+        assert_eq!(lines.last().map(String::as_str), Some("readyok"));
+    }
+
+    #[test]
+    fn plays_mate_from_a_fen_with_moves() {
+        let lines = session(
+            "position fen 6k1/5ppp/8/8/8/8/8/R5K1 b - - 0 1 moves g8h8 g1g2 h8g8\ngo depth 3\nisready\n",
+        );
+        assert!(lines.iter().any(|line| line.starts_with("info depth 1 ")));
+        assert!(lines.iter().any(|line| line.contains("score mate 1")));
+        assert!(lines.contains(&"bestmove a1a8".to_string()));
+    }
+
+    #[test]
+    fn startpos_moves_and_stop() {
+        let lines = session("position startpos moves e2e4 e7e5\ngo infinite\nstop\n");
+        let bestmove = lines
+            .iter()
+            .find_map(|line| line.strip_prefix("bestmove "))
+            .expect("bestmove after stop");
+        let mut gamestate = GameState::new();
+        for text in ["e2e4", "e7e5"] {
+            let legal_move = gamestate.parse_uci_move(text).unwrap();
+            gamestate.move_piece(legal_move);
+        }
+        assert!(gamestate.parse_uci_move(bestmove).is_some());
+    }
+
+    #[test]
+    fn reports_illegal_moves() {
+        let lines = session("position startpos moves e2e5\n");
+        assert_eq!(lines, ["info string illegal move \"e2e5\""]);
+
+        let lines = session(
+            "position startpos\nposition fen 6k1/5ppp/8/8/8/8/8/R5K1 b - - 0 1 moves g8h8 e2e4\ngo depth 2\nisready\n",
+        );
+        assert_eq!(lines[0], "info string illegal move \"e2e4\"");
+        assert!(lines.contains(&"bestmove a1a8".to_string()));
+    }
+
+    #[test]
+    fn clock_time_is_budgeted() {
+        let (limits, infinite) = parse_go(
+            "wtime 1000 btime 60000 winc 0 binc 1000".split_whitespace(),
+            Color::Black,
+        );
+        assert!(!infinite);
+        assert_eq!(limits.movetime, Some(Duration::from_millis(2750)));
+        let (limits, _) = parse_go("wtime 30 btime 30".split_whitespace(), Color::White);
+        assert_eq!(limits.movetime, Some(Duration::from_millis(1)));
+    }
+
+    #[cfg(unix)]
+    fn mock_engine(name: &str, reply: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("chess-mock-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("commands.log");
+        let script = dir.join("engine.sh");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\n\
+                 while read -r line; do\n\
+                 echo \"$line\" >> '{}'\n\
+                 case \"$line\" in\n\
+                 uci) echo 'id name Mock Engine'; echo 'option name Hash type spin'; echo uciok ;;\n\
+                 isready) echo readyok ;;\n\
+                 go*) echo 'info depth 1 score cp 0'; echo 'bestmove {reply}' ;;\n\
+                 quit) exit 0 ;;\n\
+                 esac\n\
+                 done\n",
+                log.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        (script, log)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn client_plays_the_engine_reply() {
+        let (script, log) = mock_engine("reply", "e7e5");
+        let mut gamestate = GameState::new();
+        gamestate.move_piece(gamestate.parse_uci_move("e2e4").unwrap());
+
+        let mut engine = UciEngine::spawn(&script).unwrap();
+        assert_eq!(engine.name(), Some("Mock Engine"));
+        engine.new_game().unwrap();
+        let reply = engine
+            .best_move(&gamestate, Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(reply.to_string(), "e7e5");
+        drop(engine);
+
+        let commands = std::fs::read_to_string(&log).unwrap();
+        std::fs::remove_dir_all(script.parent().unwrap()).unwrap();
+        let commands: Vec<&str> = commands.lines().collect();
+        assert_eq!(
+            commands,
+            [
+                "uci",
+                "isready",
+                "ucinewgame",
+                "isready",
+                "position startpos moves e2e4",
+                "go movetime 100",
+                "quit"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn client_rejects_illegal_replies() {
+        let (script, _) = mock_engine("illegal", "e2e5");
+        let mut engine = UciEngine::spawn(&script).unwrap();
+        let error = engine
+            .best_move(&GameState::new(), Duration::from_millis(10))
+            .unwrap_err();
+        assert!(matches!(error, UciError::IllegalMove(ref text) if text == "e2e5"));
+        drop(engine);
+        std::fs::remove_dir_all(script.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn position_command_from_fen_with_castling() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let mut gamestate = GameState::from_fen(fen).unwrap();
+        gamestate.move_piece(Move::new((7, 4), (7, 7)));
+        assert_eq!(
+            position_command(&gamestate),
+            format!("position fen {fen} moves e1g1")
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use crate::BOARD_LENGTH;
+use std::fmt;
+use std::hash::Hash;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlayStatus {
+    Continue,
+    Win(Color, GameEndReason),
+    Draw(GameEndReason),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GameEndReason {
+    Checkmate,
+    Resignation,
+    Stalemate,
+    FiftyMoveRule,
+    SeventyFiveMoveRule,
+    Repetition,
+    InsufficientMaterial,
+    Agreement,
+    Timeout,
+}
+
+impl fmt::Display for GameEndReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use GameEndReason::*;
+        match self {
+            Checkmate => write!(f, "checkmate"),
+            Resignation => write!(f, "resignation"),
+            Stalemate => write!(f, "stalemate"),
+            FiftyMoveRule => write!(f, "the fifty-move rule"),
+            SeventyFiveMoveRule => write!(f, "the seventy-five-move rule"),
+            Repetition => write!(f, "repetition"),
+            InsufficientMaterial => write!(f, "insufficient material"),
+            Agreement => write!(f, "agreement"),
+            Timeout => write!(f, "timeout"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RepetitionRule {
+    Threefold,
+    Fivefold,
+}
+
+impl RepetitionRule {
+    pub fn automatic_draw(self) -> usize {
+        match self {
+            RepetitionRule::Threefold => 3,
+            RepetitionRule::Fivefold => 5,
+        }
+    }
+}
+
+pub type Point = (i32, i32);
+
+pub fn in_bounds((x, y): Point) -> bool {
+    x >= 0 && y >= 0 && x < BOARD_LENGTH && y < BOARD_LENGTH
+}
+
+pub fn point_to_square((x, y): Point) -> String {
+    format!("{}{}", (b'a' + y as u8) as char, BOARD_LENGTH - x)
+}
+
+pub fn square_to_point(square: &str) -> Option<Point> {
+    let mut chars = square.chars();
+    let file = chars.next()?;
+    let rank = chars.next()?.to_digit(10)? as i32;
+    if chars.next().is_some() || !('a'..='h').contains(&file) {
+        return None;
+    }
+    let point = (BOARD_LENGTH - rank, file as i32 - 'a' as i32);
+    in_bounds(point).then_some(point)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub src: Point,
+    pub dst: Point,
+    pub promotion: Option<PieceState>,
+}
+
+impl Move {
+    pub fn new(src: Point, dst: Point) -> Self {
+        Self {
+            src,
+            dst,
+            promotion: None,
+        }
+    }
+
+    pub fn with_promotion(src: Point, dst: Point, promotion: PieceState) -> Self {
+        Self {
+            src,
+            dst,
+            promotion: Some(promotion),
+        }
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            point_to_square(self.src),
+            point_to_square(self.dst)
+        )?;
+        if let Some(promotion) = self.promotion {
+            write!(f, "{}", promotion.to_char().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayedMove {
+    pub played: Move,
+    pub san: String,
+}
+
+#[derive(Debug, Hash, Eq, PartialEq, Copy, Clone)]
+pub enum Color {
+    Black,
+    White,
+}
+
+impl Color {
+    pub fn opposite(self) -> Self {
+        if self == Color::Black {
+            Color::White
+        } else {
+            Color::Black
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Color::Black => write!(f, "Black"),
+            Color::White => write!(f, "White"),
+        }
+    }
+}
+
+#[derive(Eq, Hash, Debug, Copy, Clone, PartialEq)]
+pub enum PieceState {
+    King,
+    Queen,
+    Rook,
+    Bishop,
+    Knight,
+    Pawn,
+}
+
+impl PieceState {
+    pub fn to_char(self) -> char {
+        use PieceState::*;
+        match self {
+            King => 'K',
+            Queen => 'Q',
+            Rook => 'R',
+            Bishop => 'B',
+            Knight => 'N',
+            Pawn => 'P',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        use PieceState::*;
+        match c.to_ascii_uppercase() {
+            'K' => Some(King),
+            'Q' => Some(Queen),
+            'R' => Some(Rook),
+            'B' => Some(Bishop),
+            'N' => Some(Knight),
+            'P' => Some(Pawn),
+            _ => None,
+        }
+    }
+}
+
+pub const PROMOTION_PIECES: [PieceState; 4] = [
+    PieceState::Queen,
+    PieceState::Rook,
+    PieceState::Bishop,
+    PieceState::Knight,
+];
+
+#[derive(Eq, Copy, Clone, PartialEq, Debug)]
+pub struct Piece {
+    pub state: PieceState,
+    pub color: Color,
+    pub has_moved: bool,
+}
+
+impl Piece {
+    pub fn new(state: PieceState, black: bool) -> Self {
+        Self {
+            state,
+            color: if black { Color::Black } else { Color::White },
+            has_moved: false,
+        }
+    }
+}
+
+#[derive(Eq, Copy, Clone, PartialEq, Debug)]
+pub enum Tile {
+    Empty,
+    Piece(Piece),
+}
+
+pub type Board = Vec<Vec<Tile>>;
+
+pub const fn flip(x: i32) -> i32 {
+    7 - x
+}
+
+pub fn flip_rank(rank: i32, black: bool) -> i32 {
+    if black {
+        rank
+    } else {
+        flip(rank)
+    }
+}
+
+pub fn file_to_piece(file: i32) -> PieceState {
+    use PieceState::*;
+
+    const FLIP_ZERO: i32 = flip(0);
+    const FLIP_ONE: i32 = flip(1);
+    const FLIP_TWO: i32 = flip(2);
+
+    match file {
+        0 | FLIP_ZERO => Rook,
+        1 | FLIP_ONE => Knight,
+        2 | FLIP_TWO => Bishop,
+        3 => Queen,
+        4 => King,
+        _ => panic!("Should never be any other number"),
+    }
+}
@@ -0,0 +1,530 @@
+#!/usr/bin/env python3
+"""Generate the 3-piece Syzygy test fixtures (KQvK, KRvK, KBvK, KNvK, KPvK).
+
+Solves each ending by retrograde analysis and writes the WDL and DTZ results
+in the .rtbw/.rtbz format read by chess-core's syzygy module, compressed with
+RePair and canonical Huffman codes like the published tables.
+
+    python3 generate.py OUTPUT_DIRECTORY [EXPECTED_VALUES_FILE]
+
+The optional second argument dumps every position as "fen;wdl;dtz" so the
+decoder can be checked against the solver.
+"""
+import heapq
+import os
+import sys
+from collections import Counter
+
+WDL_MAGIC = bytes([0x71, 0xE8, 0x23, 0x5D])
+DTZ_MAGIC = bytes([0xD7, 0x66, 0x0C, 0xA5])
+FLAG_MAPPED, FLAG_WIN_PLIES, FLAG_SINGLE = 2, 4, 128
+CODES = {"P": 1, "N": 2, "B": 3, "R": 4, "Q": 5, "K": 6}
+
+fl = lambda s: s % 8
+rk = lambda s: s // 8
+
+
+def adjacent(a, b):
+    return a != b and abs(fl(a) - fl(b)) <= 1 and abs(rk(a) - rk(b)) <= 1
+
+
+KING = [[t for t in range(64) if adjacent(s, t)] for s in range(64)]
+KNIGHT = [
+    [t for t in range(64) if sorted((abs(fl(s) - fl(t)), abs(rk(s) - rk(t)))) == [1, 2]]
+    for s in range(64)
+]
+ROOK_DIRS = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+BISHOP_DIRS = [(1, 1), (1, -1), (-1, 1), (-1, -1)]
+
+
+def slide(s, dirs, occ):
+    out = []
+    for df, dr in dirs:
+        f, r = fl(s) + df, rk(s) + dr
+        while 0 <= f < 8 and 0 <= r < 8:
+            t = r * 8 + f
+            out.append(t)
+            if t in occ:
+                break
+            f, r = f + df, r + dr
+    return out
+
+
+def attacks(kind, s, occ):
+    if kind == "Q":
+        return slide(s, ROOK_DIRS + BISHOP_DIRS, occ)
+    if kind == "R":
+        return slide(s, ROOK_DIRS, occ)
+    if kind == "B":
+        return slide(s, BISHOP_DIRS, occ)
+    if kind == "N":
+        return KNIGHT[s]
+    if kind == "P":
+        return [s + 8 + d for d in (-1, 1) if 0 <= fl(s) + d < 8 and s + 8 + d < 64]
+    raise ValueError(kind)
+
+
+def pid(wk, bk, x):
+    return (wk * 64 + bk) * 64 + x
+
+
+N = 64 * 64 * 64
+
+
+def legal(kind, wk, bk, x, white):
+    if len({wk, bk, x}) < 3 or adjacent(wk, bk):
+        return False
+    if kind == "P" and not 1 <= rk(x) <= 6:
+        return False
+    if white and bk in attacks(kind, x, {wk, bk, x}):
+        return False
+    return True
+
+
+def solve(kind, sub):
+    legal_w = bytearray(N)
+    legal_b = bytearray(N)
+    succ_w = [None] * N  # list of (black pid, zeroing)
+    exit_w = bytearray(N)  # promotion that wins outright
+    succ_b = [None] * N
+    cap_b = bytearray(N)
+    check_b = bytearray(N)
+    for wk in range(64):
+        for bk in range(64):
+            for x in range(64):
+                p = pid(wk, bk, x)
+                if legal(kind, wk, bk, x, True):
+                    legal_w[p] = 1
+                    moves = []
+                    for t in KING[wk]:
+                        if t != x and not adjacent(t, bk):
+                            moves.append((pid(t, bk, x), False))
+                    if kind == "P":
+                        t = x + 8
+                        if t not in (wk, bk):
+                            if rk(t) == 7:
+                                for promoted in "QRBN":
+                                    if sub[promoted][pid(wk, bk, t)]:
+                                        exit_w[p] = 1
+                            else:
+                                moves.append((pid(wk, bk, t), True))
+                                if rk(x) == 1 and t + 8 not in (wk, bk):
+                                    moves.append((pid(wk, bk, t + 8), True))
+                    else:
+                        for t in attacks(kind, x, {wk, bk, x}):
+                            if t not in (wk, bk):
+                                moves.append((pid(wk, bk, t), False))
+                    succ_w[p] = moves
+                if legal(kind, wk, bk, x, False):
+                    legal_b[p] = 1
+                    occ = {wk, bk, x}
+                    check_b[p] = bk in attacks(kind, x, occ)
+                    moves = []
+                    for t in KING[bk]:
+                        if t == wk or adjacent(t, wk):
+                            continue
+                        if t == x:
+                            cap_b[p] = 1
+                            continue
+                        if t in attacks(kind, x, {wk, x}):
+                            continue
+                        moves.append(pid(wk, t, x))
+                    succ_b[p] = moves
+
+    pred_w = [[] for _ in range(N)]  # black pid -> [(white pid, zeroing)]
+    pred_b = [[] for _ in range(N)]  # white pid -> [black pid]
+    for p in range(N):
+        if legal_w[p]:
+            for q, z in succ_w[p]:
+                pred_w[q].append((p, z))
+        if legal_b[p]:
+            for q in succ_b[p]:
+                pred_b[q].append(p)
+
+    won_w = bytearray(N)
+    lost_b = bytearray(N)
+    mated = bytearray(N)
+    queue = []
+    for p in range(N):
+        if legal_b[p] and not succ_b[p] and not cap_b[p] and check_b[p]:
+            lost_b[p] = mated[p] = 1
+            queue.append((False, p))
+        if legal_w[p] and exit_w[p]:
+            won_w[p] = 1
+            queue.append((True, p))
+    count = [len(succ_b[p]) if legal_b[p] else 0 for p in range(N)]
+    i = 0
+    while i < len(queue):
+        white, p = queue[i]
+        i += 1
+        if white:
+            for b in pred_b[p]:
+                count[b] -= 1
+                if count[b] == 0 and not cap_b[b] and not lost_b[b]:
+                    lost_b[b] = 1
+                    queue.append((False, b))
+        else:
+            for w, _ in pred_w[p]:
+                if not won_w[w]:
+                    won_w[w] = 1
+                    queue.append((True, w))
+
+    dz_w = [-1] * N
+    dz_b = [-1] * N
+    zeroing = bytearray(N)
+    buckets = {0: [], 1: []}
+    for p in range(N):
+        if mated[p]:
+            dz_b[p] = 0
+            buckets[0].append((False, p))
+        if won_w[p] and (exit_w[p] or any(z and lost_b[q] for q, z in succ_w[p])):
+            dz_w[p] = 1
+            zeroing[p] = 1
+            buckets[1].append((True, p))
+    count = [len(succ_b[p]) if lost_b[p] else 0 for p in range(N)]
+    d = 0
+    while d in buckets:
+        for white, p in buckets.pop(d):
+            if white:
+                for b in pred_b[p]:
+                    if lost_b[b]:
+                        count[b] -= 1
+                        if count[b] == 0:
+                            dz_b[b] = d + 1
+                            buckets.setdefault(d + 1, []).append((False, b))
+            else:
+                for w, z in pred_w[p]:
+                    if not z and won_w[w] and dz_w[w] == -1:
+                        dz_w[w] = d + 1
+                        buckets.setdefault(d + 1, []).append((True, w))
+        d += 1
+    for p in range(N):
+        assert not won_w[p] or dz_w[p] > 0, p
+        assert not lost_b[p] or dz_b[p] >= 0, p
+    return dict(
+        won_w=won_w, lost_b=lost_b, dz_w=dz_w, dz_b=dz_b,
+        legal_w=legal_w, legal_b=legal_b, mated=mated, zeroing=zeroing,
+    )
+
+
+# --- indexing, mirroring the decoder -------------------------------------
+
+offd = lambda s: rk(s) - fl(s)
+fdiag = lambda s: ((s >> 3) | (s << 3)) & 63
+MAP_B1H1H7 = {}
+for s in range(64):
+    if offd(s) < 0:
+        MAP_B1H1H7[s] = len(MAP_B1H1H7)
+MAP_A1D1D4 = {}
+tri = [s for s in range(64) if fl(s) <= 3 and rk(s) <= 3]
+for s in [s for s in tri if offd(s) < 0] + [s for s in tri if offd(s) == 0]:
+    MAP_A1D1D4[s] = len(MAP_A1D1D4)
+
+
+def index_pieces(sq):
+    sq = list(sq)
+    if fl(sq[0]) > 3:
+        sq = [s ^ 7 for s in sq]
+    if rk(sq[0]) > 3:
+        sq = [s ^ 56 for s in sq]
+    for i in range(3):
+        o = offd(sq[i])
+        if o == 0:
+            continue
+        if o > 0:
+            sq[i:] = [fdiag(s) for s in sq[i:]]
+        break
+    a1 = int(sq[1] > sq[0])
+    a2 = int(sq[2] > sq[0]) + int(sq[2] > sq[1])
+    if offd(sq[0]):
+        return (MAP_A1D1D4[sq[0]] * 63 + sq[1] - a1) * 62 + sq[2] - a2
+    if offd(sq[1]):
+        return (6 * 63 + rk(sq[0]) * 28 + MAP_B1H1H7[sq[1]]) * 62 + sq[2] - a2
+    if offd(sq[2]):
+        return (6 * 63 * 62 + 4 * 28 * 62 + rk(sq[0]) * 7 * 28
+                + (rk(sq[1]) - a1) * 28 + MAP_B1H1H7[sq[2]])
+    return (6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + rk(sq[0]) * 7 * 6
+            + (rk(sq[1]) - a1) * 6 + rk(sq[2]) - a2)
+
+
+def index_pawn(sq):
+    sq = list(sq)
+    if fl(sq[0]) > 3:
+        sq = [s ^ 7 for s in sq]
+    idx = rk(sq[0]) - 1
+    idx += (sq[1] - int(sq[1] > sq[0])) * 6
+    idx += (sq[2] - int(sq[2] > sq[0]) - int(sq[2] > sq[1])) * 378
+    return idx
+
+
+# --- compression ---------------------------------------------------------
+
+
+def fill_dont_care(values):
+    first = next(v for v in values if v is not None)
+    out, last = [], first
+    for v in values:
+        last = v if v is not None else last
+        out.append(last)
+    return out
+
+
+def repair(seq, leaves):
+    symbols = [("leaf", v) for v in leaves]
+    lens = [1] * len(leaves)
+    while len(symbols) < 4000:
+        counts = Counter(zip(seq, seq[1:]))
+        best = None
+        for pair, n in counts.most_common():
+            if lens[pair[0]] + lens[pair[1]] <= 256:
+                best = (pair, n)
+                break
+        if best is None or best[1] < 8:
+            break
+        (a, b), _ = best
+        new = len(symbols)
+        symbols.append(("pair", a, b))
+        lens.append(lens[a] + lens[b])
+        out, i = [], 0
+        while i < len(seq):
+            if i + 1 < len(seq) and seq[i] == a and seq[i + 1] == b:
+                out.append(new)
+                i += 2
+            else:
+                out.append(seq[i])
+                i += 1
+        seq = out
+    return seq, symbols, lens
+
+
+def huffman_lengths(freqs):
+    heap = [(f, i, [i]) for i, f in enumerate(freqs)]
+    heapq.heapify(heap)
+    lengths = [0] * len(freqs)
+    if len(heap) == 1:
+        return [1]
+    tie = len(freqs)
+    while len(heap) > 1:
+        f1, _, s1 = heapq.heappop(heap)
+        f2, _, s2 = heapq.heappop(heap)
+        for s in s1 + s2:
+            lengths[s] += 1
+        heapq.heappush(heap, (f1 + f2, tie, s1 + s2))
+        tie += 1
+    assert max(lengths) <= 32
+    return lengths
+
+
+def compress(values, flags, block_log=6, span_log=8):
+    distinct = sorted(set(values))
+    if len(distinct) == 1:
+        return dict(header=bytes([flags | FLAG_SINGLE, distinct[0]]), sparse=b"", blens=b"", data=b"")
+    leaf_of = {v: i for i, v in enumerate(distinct)}
+    seq, symbols, lens = repair([leaf_of[v] for v in values], distinct)
+    freq = Counter(seq)
+    lengths = huffman_lengths([max(freq[s], 1) for s in range(len(symbols))])
+    order = sorted(range(len(symbols)), key=lambda s: (-lengths[s], s))
+    number = {old: new for new, old in enumerate(order)}
+    lo, hi = min(lengths), max(lengths)
+    nlen = hi - lo + 1
+    n = [sum(1 for l in lengths if l == lo + j) for j in range(nlen)]
+    lowest = [sum(1 for l in lengths if l > lo + j) for j in range(nlen)]
+    base = [0] * nlen
+    for j in range(nlen - 2, -1, -1):
+        assert (base[j + 1] + n[j + 1]) % 2 == 0
+        base[j] = (base[j + 1] + n[j + 1]) // 2
+
+    def code(old):
+        s = number[old]
+        j = lengths[old] - lo
+        return base[j] + s - lowest[j], lengths[old]
+
+    block_bits = (1 << block_log) * 8
+    blocks, current, bits, count = [], [], 0, 0
+    for s in seq:
+        c, l = code(s)
+        if bits + l > block_bits or count + lens[s] > 65536:
+            blocks.append((current, count))
+            current, bits, count = [], 0, 0
+        current.append((c, l))
+        bits += l
+        count += lens[s]
+    blocks.append((current, count))
+
+    data = bytearray()
+    for codes, _ in blocks:
+        acc, nbits = 0, 0
+        for c, l in codes:
+            acc = (acc << l) | c
+            nbits += l
+        acc <<= block_bits - nbits
+        data += acc.to_bytes(block_bits // 8, "big")
+
+    starts, total = [], 0
+    for _, count in blocks:
+        starts.append(total)
+        total += count
+    assert total == len(values)
+    span = 1 << span_log
+    sparse = bytearray()
+    b = 0
+    for k in range((len(values) + span - 1) // span):
+        t = k * span + span // 2
+        target = min(t, len(values) - 1)
+        while b + 1 < len(starts) and starts[b + 1] <= target:
+            b += 1
+        sparse += b.to_bytes(4, "little") + (t - starts[b]).to_bytes(2, "little")
+    blens = b"".join((count - 1).to_bytes(2, "little") for _, count in blocks)
+
+    header = bytearray([flags, block_log, span_log, 0])
+    header += len(blocks).to_bytes(4, "little")
+    header += bytes([hi, lo])
+    for j in range(nlen):
+        header += lowest[j].to_bytes(2, "little")
+    header += len(symbols).to_bytes(2, "little")
+    for new in range(len(symbols)):
+        sym = symbols[order[new]]
+        left, right = (sym[1], 0xFFF) if sym[0] == "leaf" else (number[sym[1]], number[sym[2]])
+        header += bytes([left & 0xFF, (left >> 8) | ((right & 0xF) << 4), right >> 4])
+    if len(symbols) & 1:
+        header.append(0)
+    return dict(header=bytes(header), sparse=bytes(sparse), blens=blens, data=bytes(data))
+
+
+def write_table(path, magic, flags, files):
+    out = bytearray(magic) + bytes([flags])
+    for f in files:
+        out.append(0)
+        out += bytes(c | (c << 4) for c in f["pieces"])
+    if len(out) & 1:
+        out.append(0)
+    for f in files:
+        for side in f["sides"]:
+            out += side["header"]
+    if any("map" in f for f in files):
+        for f in files:
+            for lst in f["map"]:
+                out.append(len(lst))
+                out += bytes(lst)
+        if len(out) & 1:
+            out.append(0)
+    for key in ("sparse", "blens"):
+        for f in files:
+            for side in f["sides"]:
+                out += side[key]
+    for f in files:
+        for side in f["sides"]:
+            if side["data"]:
+                out += bytes(-len(out) % 64)
+                out += side["data"]
+    out += bytes((16 - len(out)) % 64)
+    with open(path, "wb") as handle:
+        handle.write(out)
+
+
+def build(kind, res, outdir):
+    name = f"K{kind}vK"
+    code = CODES[kind]
+    pawn = kind == "P"
+    nfiles = 4 if pawn else 1
+    size = 23436 if pawn else 31332
+    wdl = [[[None] * size for _ in range(2)] for _ in range(nfiles)]
+    dtz = [[None] * size for _ in range(nfiles)]
+    for wk in range(64):
+        for bk in range(64):
+            for x in range(64):
+                p = pid(wk, bk, x)
+                if pawn:
+                    f = min(fl(x), 7 - fl(x))
+                    idx = index_pawn([x, wk, bk])
+                else:
+                    f = 0
+                    idx = index_pieces([wk, bk, x])
+                for side, ok, val in (
+                    (0, res["legal_w"][p], 4 if res["won_w"][p] else 2),
+                    (1, res["legal_b"][p], 0 if res["lost_b"][p] else 2),
+                ):
+                    if not ok:
+                        continue
+                    old = wdl[f][side][idx]
+                    assert old is None or old == val, (name, wk, bk, x, side)
+                    wdl[f][side][idx] = val
+                if res["legal_w"][p] and res["won_w"][p] and not res["zeroing"][p]:
+                    m = res["dz_w"][p] - 1
+                    assert dtz[f][idx] in (None, m)
+                    dtz[f][idx] = m
+    pieces = [1, 6, 14] if pawn else [6, 14, code]
+    wdl_files = [
+        dict(pieces=pieces, sides=[compress(fill_dont_care(wdl[f][s]), 0) for s in range(2)])
+        for f in range(nfiles)
+    ]
+    write_table(os.path.join(outdir, name + ".rtbw"), WDL_MAGIC, 3 if pawn else 1, wdl_files)
+
+    dtz_files = []
+    for f in range(nfiles):
+        values = dtz[f]
+        if all(v is None for v in values):
+            values = [0] * size
+        freq = Counter(v for v in values if v is not None)
+        wins = [m for m, _ in freq.most_common()]
+        assert max(wins) < 256
+        mapped = [None if v is None else wins.index(v) for v in values]
+        dtz_files.append(dict(
+            pieces=pieces,
+            sides=[compress(fill_dont_care(mapped), FLAG_MAPPED | FLAG_WIN_PLIES)],
+            map=[wins, [], [], []],
+        ))
+    write_table(os.path.join(outdir, name + ".rtbz"), DTZ_MAGIC, 3 if pawn else 1, dtz_files)
+
+
+def fen(kind, wk, bk, x, white):
+    board = [["1"] * 8 for _ in range(8)]
+    for s, c in ((wk, "K"), (bk, "k"), (x, kind)):
+        board[7 - rk(s)][fl(s)] = c
+    rows = []
+    for row in board:
+        text, run = "", 0
+        for c in row:
+            if c == "1":
+                run += 1
+            else:
+                text += (str(run) if run else "") + c
+                run = 0
+        rows.append(text + (str(run) if run else ""))
+    return "/".join(rows) + (" w" if white else " b") + " - - 0 1"
+
+
+def main():
+    outdir = sys.argv[1]
+    check = sys.argv[2] if len(sys.argv) > 2 else None
+    os.makedirs(outdir, exist_ok=True)
+    results = {}
+    for kind in "QRBNP":
+        res = solve(kind, {k: results[k]["lost_b"] for k in results})
+        results[kind] = res
+        dz = [d for d in res["dz_w"] if d > 0]
+        print(kind, "won wtm", sum(res["won_w"]), "lost btm", sum(res["lost_b"]),
+              "max dtz", max(dz) if dz else 0, flush=True)
+        build(kind, res, outdir)
+    if check:
+        with open(check, "w") as out:
+            for kind in "QRP":
+                res = results[kind]
+                for wk in range(64):
+                    for bk in range(64):
+                        for x in range(64):
+                            p = pid(wk, bk, x)
+                            if res["legal_w"][p]:
+                                w = 2 if res["won_w"][p] else 0
+                                out.write(f"{fen(kind, wk, bk, x, True)};{w};{res['dz_w'][p] if w else 0}\n")
+                            if res["legal_b"][p]:
+                                if res["mated"][p]:
+                                    w, d = -2, -1
+                                elif res["lost_b"][p]:
+                                    w, d = -2, -res["dz_b"][p]
+                                else:
+                                    w, d = 0, 0
+                                out.write(f"{fen(kind, wk, bk, x, False)};{w};{d}\n")
+
+
+main()
@@ -0,0 +1,190 @@
+use crate::TILE_SIZE;
+use chess_core::util::*;
+use chess_core::GameState;
+use std::collections::HashSet;
+
+#[derive(Clone)]
+struct Selection {
+    starting_tile: Point,
+    current_point: Point,
+    piece: Piece,
+    held_down: bool,
+}
+
+#[derive(Clone)]
+pub struct Game {
+    pub state: GameState,
+    selected: Option<Selection>,
+    promotion: Option<Move>,
+}
+
+impl Game {
+    pub fn new(state: GameState) -> Self {
+        Self {
+            state,
+            selected: None,
+            promotion: None,
+        }
+    }
+
+    pub fn slected_piece_coord(&self) -> Option<Point> {
+        self.selected.as_ref().map(|s| s.starting_tile)
+    }
+
+    pub fn mouse_down(&mut self, x: i32, y: i32) {
+        let (board_x, board_y) = get_board_position((x, y));
+        if self.promotion.is_some() {
+            self.choose_promotion((board_x, board_y));
+            return;
+        }
+        if let Some(ref selected) = self.selected {
+            if self.is_valid_tile(board_x, board_y) {
+                self.play_move(Move::new(selected.starting_tile, (board_x, board_y)));
+                return;
+            }
+        }
+        self.select_tile((x, y));
+    }
+
+    pub fn mouse_up(&mut self, x: i32, y: i32) {
+        let (board_x, board_y) = get_board_position((x, y));
+        if let Some(
+            ref selected @ Selection {
+                held_down: true, ..
+            },
+        ) = self.selected
+        {
+            if self.is_valid_tile(board_x, board_y) {
+                self.play_move(Move::new(selected.starting_tile, (board_x, board_y)));
+            } else {
+                self.selected
+                    .as_mut()
+                    .expect("checked in if statement")
+                    .held_down = false;
+            }
+        }
+    }
+
+    pub fn mouse_move(&mut self, x: i32, y: i32) {
+        if let Some(ref mut selected) = self.selected {
+            selected.current_point = (x, y);
+        }
+    }
+
+    pub fn undo(&mut self) -> bool {
+        self.clear_selection();
+        self.state.undo()
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.clear_selection();
+        self.state.redo()
+    }
+
+    pub fn play(&mut self, selected_move: Move) {
+        self.clear_selection();
+        self.state.move_piece(selected_move);
+    }
+
+    pub fn pending_promotion(&self) -> Option<(Move, Color)> {
+        self.promotion
+            .map(|promotion| (promotion, self.state.players_turn()))
+    }
+
+    pub fn legal_moves(&self) -> HashSet<Point> {
+        if let Some(ref selected) = self.selected {
+            self.state.piece_legal_moves(selected.starting_tile)
+        } else {
+            HashSet::new()
+        }
+    }
+
+    pub fn select_tile(&mut self, (pos_x, pos_y): (i32, i32)) {
+        let (board_x, board_y) = get_board_position((pos_x, pos_y));
+        if !in_bounds((board_x, board_y)) {
+            return;
+        }
+        if let Some(piece) = self.state.get_piece((board_x, board_y)) {
+            if piece.color == self.state.players_turn() {
+                self.selected = Some(Selection {
+                    starting_tile: (board_x, board_y),
+                    current_point: (pos_x, pos_y),
+                    piece,
+                    held_down: true,
+                });
+            }
+        }
+    }
+
+    pub fn get_moving_piece(&self) -> Option<(Piece, Point)> {
+        if let Some(Selection {
+            held_down,
+            piece,
+            current_point,
+            ..
+        }) = self.selected
+        {
+            if held_down {
+                return Some((piece, current_point));
+            }
+        }
+        None
+    }
+
+    pub fn get_selected_tile(&self) -> Option<Point> {
+        self.selected.as_ref().map(
+            |Selection {
+                 current_point: (x, y),
+                 ..
+             }| get_board_position((*x, *y)),
+        )
+    }
+
+    fn clear_selection(&mut self) {
+        self.selected = None;
+        self.promotion = None;
+    }
+
+    fn is_valid_tile(&self, x: i32, y: i32) -> bool {
+        let tile = (x, y);
+        self.legal_moves().contains(&tile)
+    }
+
+    fn play_move(&mut self, selected_move: Move) {
+        self.selected = None;
+        if self.state.is_promotion(&selected_move) && selected_move.promotion.is_none() {
+            self.promotion = Some(selected_move);
+            return;
+        }
+        self.state.move_piece(selected_move);
+    }
+
+    fn choose_promotion(&mut self, point: Point) {
+        if let Some(promotion) = self.promotion.take() {
+            for (tile, state) in promotion_picker_tiles(promotion.dst, self.state.players_turn()) {
+                if tile == point {
+                    self.state.move_piece(Move::with_promotion(
+                        promotion.src,
+                        promotion.dst,
+                        state,
+                    ));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+pub fn get_board_position((x, y): (i32, i32)) -> (i32, i32) {
+    (y / TILE_SIZE, x / TILE_SIZE)
+}
+
+pub fn promotion_picker_tiles((x, y): Point, color: Color) -> [(Point, PieceState); 4] {
+    let direction = if color == Color::Black { -1 } else { 1 };
+    let mut i = 0;
+    PROMOTION_PIECES.map(|state| {
+        let tile = ((x + direction * i, y), state);
+        i += 1;
+        tile
+    })
+}
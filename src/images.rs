@@ -1,4 +1,4 @@
-use crate::util::*;
+use chess_core::util::*;
 use sdl2::image::LoadTexture;
 use sdl2::render::Texture;
 use sdl2::render::TextureCreator;
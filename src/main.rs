@@ -1,32 +1,120 @@
 extern crate sdl2;
 
-use crate::util::*;
-use gamestate::GameState;
+use chess_core::clock::TimeControl;
+use chess_core::engine::Engine;
+use chess_core::eval::evaluate_terms;
+use chess_core::pgn::load_pgn;
+use chess_core::polyglot::Book;
+use chess_core::syzygy::Tablebase;
+use chess_core::uci::{run_uci, UciEngine};
+use chess_core::util::*;
+use chess_core::{GameState, BOARD_LENGTH};
+use game::Game;
 use images::Images;
-use renderer::Renderer;
+use opponent::Opponent;
+use renderer::{GameOverChoice, Renderer};
 use sdl2::event::Event;
 use sdl2::image::InitFlag;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::sync::atomic::Ordering;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-const BOARD_LENGTH: i32 = 8;
 const TILE_SIZE: i32 = 96;
+const PANEL_WIDTH: i32 = 3 * TILE_SIZE;
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(1);
 
+mod game;
 mod images;
+mod opponent;
 mod renderer;
-mod util;
-
-mod gamestate;
 
 fn main() -> Result<(), String> {
+    match std::env::args().nth(1).as_deref() {
+        Some("perft") => return perft(std::env::args().skip(2)),
+        Some("uci") => return run_uci(io::stdin().lock(), io::stdout()).map_err(|e| e.to_string()),
+        _ => {}
+    }
+
+    let mut fen = None;
+    let mut pgn = None;
+    let mut repetition_rule = RepetitionRule::Threefold;
+    let mut computer = None;
+    let mut engine_path = None;
+    let mut movetime = DEFAULT_MOVE_TIME;
+    let mut book = None;
+    let mut show_book = false;
+    let mut tablebase = None;
+    let mut time_control = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fen" => fen = Some(args.next().ok_or("--fen requires a FEN string")?),
+            "--pgn" => pgn = Some(args.next().ok_or("--pgn requires a file path")?),
+            "--fivefold" => repetition_rule = RepetitionRule::Fivefold,
+            "--computer" => {
+                computer = Some(match args.next().as_deref() {
+                    Some("white") => Color::White,
+                    Some("black") => Color::Black,
+                    _ => return Err("--computer requires \"white\" or \"black\"".to_string()),
+                })
+            }
+            "--engine" => engine_path = Some(args.next().ok_or("--engine requires a path")?),
+            "--movetime" => {
+                movetime = args
+                    .next()
+                    .and_then(|millis| millis.parse().ok())
+                    .map(Duration::from_millis)
+                    .ok_or("--movetime requires a number of milliseconds")?
+            }
+            "--book" => {
+                let path = args.next().ok_or("--book requires a path")?;
+                book = Some(Book::open(&path).map_err(|e| format!("could not read {path}: {e}"))?);
+            }
+            "--show-book" => show_book = true,
+            "--clock" => {
+                let spec = args
+                    .next()
+                    .ok_or("--clock requires a time control such as 5, 3+2 or 40/90:30+30")?;
+                time_control = Some(spec.parse::<TimeControl>().map_err(|e| e.to_string())?);
+            }
+            "--syzygy" => {
+                let path = args.next().ok_or("--syzygy requires a directory")?;
+                tablebase = Some(
+                    Tablebase::open(&path).map_err(|e| format!("could not read {path}: {e}"))?,
+                );
+            }
+            _ => return Err(format!("unknown argument \"{arg}\"")),
+        }
+    }
+
+    let engine = Engine::new();
+    let stop_search = engine.stop_handle();
+    let mut opponent = Some(match engine_path {
+        Some(path) => {
+            let mut external =
+                UciEngine::spawn(&path).map_err(|e| format!("could not start {path}: {e}"))?;
+            external.new_game().map_err(|e| e.to_string())?;
+            println!("Playing against {}", external.name().unwrap_or(&path));
+            computer = computer.or(Some(Color::Black));
+            Opponent::External(external)
+        }
+        None => Opponent::BuiltIn(Box::new(engine)),
+    });
+
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
 
     let _image_context = sdl2::image::init(InitFlag::PNG);
+    let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string())?;
 
     let window_length = (BOARD_LENGTH * TILE_SIZE) as u32;
 
     let window = video_subsystem
-        .window("chess", window_length, window_length)
+        .window("chess", window_length + PANEL_WIDTH as u32, window_length)
         .position_centered()
         .opengl()
         .build()
@@ -40,56 +128,393 @@ fn main() -> Result<(), String> {
 
     let texture_creator = canvas.texture_creator();
 
-    let mut renderer = Renderer::new(canvas, Images::new(&texture_creator)?)?;
-    let mut gamestate = GameState::new();
+    let mut renderer = Renderer::new(
+        canvas,
+        Images::new(&texture_creator)?,
+        &texture_creator,
+        &ttf_context,
+    )?;
+    let mut gamestate = match fen {
+        Some(fen) => GameState::from_fen(&fen).map_err(|e| e.to_string())?,
+        None => GameState::new(),
+    };
+    gamestate.set_repetition_rule(repetition_rule);
+    if let Some(control) = time_control {
+        gamestate.set_time_control(control);
+    }
+    let mut game = Game::new(gamestate);
 
-    let mut game_continue = true;
+    let mut replay = match pgn {
+        Some(path) => {
+            let game = load_pgn(&path)?
+                .into_iter()
+                .next()
+                .ok_or(format!("no games found in {path}"))?;
+            println!(
+                "{} vs {} ({})",
+                game.tag("White").unwrap_or("?"),
+                game.tag("Black").unwrap_or("?"),
+                game.result
+            );
+            let positions = game
+                .positions()
+                .into_iter()
+                .map(Game::new)
+                .collect::<Vec<_>>();
+            Some((positions, 0usize))
+        }
+        None => None,
+    };
+
+    let mut game_continue = replay.is_none();
+    let mut thinking: Option<JoinHandle<(Opponent, Option<Move>)>> = None;
+    let mut viewing: Option<(usize, Game)> = None;
+    let mut show_analysis = false;
+    let mut analysis: Option<(u64, Vec<String>)> = None;
 
     'mainloop: loop {
+        let mut choice = None;
         for event in sdl_context.event_pump()?.poll_iter() {
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Option::Some(Keycode::Escape),
                     ..
-                } => break 'mainloop,
+                } => {
+                    stop_search.store(true, Ordering::Relaxed);
+                    break 'mainloop;
+                }
+
+                Event::KeyDown {
+                    keycode: Option::Some(Keycode::E),
+                    ..
+                } => {
+                    show_analysis = !show_analysis;
+                }
+
+                Event::KeyDown {
+                    keycode: Option::Some(Keycode::Left),
+                    ..
+                } => match replay {
+                    Some((_, ref mut index)) => *index = index.saturating_sub(1),
+                    None => {
+                        let plies = viewing
+                            .as_ref()
+                            .map_or(game.state.history().len(), |(plies, _)| *plies);
+                        viewing = view(&game, plies.saturating_sub(1));
+                    }
+                },
+
+                Event::KeyDown {
+                    keycode: Option::Some(Keycode::Right),
+                    ..
+                } => match replay {
+                    Some((ref positions, ref mut index)) => {
+                        *index = (*index + 1).min(positions.len() - 1)
+                    }
+                    None => {
+                        if let Some((plies, _)) = viewing {
+                            viewing = view(&game, plies + 1);
+                        }
+                    }
+                },
 
-                Event::MouseMotion { x, y, .. } if game_continue => {
-                    gamestate.mouse_move(x, y);
+                Event::KeyDown {
+                    keycode: Option::Some(Keycode::S),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    let timestamp = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |elapsed| elapsed.as_secs());
+                    let path = format!("game-{timestamp}.pgn");
+                    match game.state.save_pgn(&path) {
+                        Ok(()) => println!("Saved game to {path}"),
+                        Err(e) => println!("Could not save {path}: {e}"),
+                    }
                 }
 
-                Event::MouseButtonDown { x, y, .. } if game_continue => {
-                    gamestate.mouse_down(x, y);
+                Event::KeyDown {
+                    keycode: Option::Some(Keycode::Z),
+                    keymod,
+                    ..
+                } if replay.is_none()
+                    && thinking.is_none()
+                    && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) =>
+                {
+                    viewing = None;
+                    game_continue |= game.undo();
+                    if computer == Some(game.state.players_turn()) {
+                        game.undo();
+                    }
                 }
 
-                Event::MouseButtonUp { x, y, .. } if game_continue => {
-                    gamestate.mouse_up(x, y);
+                Event::KeyDown {
+                    keycode: Option::Some(Keycode::Y),
+                    keymod,
+                    ..
+                } if replay.is_none()
+                    && thinking.is_none()
+                    && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) =>
+                {
+                    viewing = None;
+                    game.redo();
+                    if computer == Some(game.state.players_turn()) {
+                        game.redo();
+                    }
+                }
+
+                Event::KeyDown {
+                    keycode: Option::Some(Keycode::D),
+                    ..
+                } if game_continue && computer != Some(game.state.players_turn()) => {
+                    let claimed = game.state.claim_draw();
+                    if !claimed {
+                        println!("No draw can be claimed in this position");
+                    }
+                }
+
+                Event::KeyDown {
+                    keycode: Option::Some(Keycode::R),
+                    keymod,
+                    ..
+                } if game_continue
+                    && computer != Some(game.state.players_turn())
+                    && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) =>
+                {
+                    game.state.resign()
+                }
+
+                Event::KeyDown {
+                    keycode: Option::Some(Keycode::N),
+                    ..
+                } if !game_continue => choice = Some(GameOverChoice::NewGame),
+
+                Event::KeyDown {
+                    keycode: Option::Some(Keycode::C),
+                    ..
+                } if !game_continue => choice = Some(GameOverChoice::Rematch),
+
+                Event::KeyDown {
+                    keycode: Option::Some(Keycode::R),
+                    ..
+                } if !game_continue && replay.is_none() => choice = Some(GameOverChoice::Review),
+
+                Event::KeyDown {
+                    keycode: Option::Some(Keycode::A),
+                    keymod,
+                    ..
+                } if game_continue
+                    && computer != Some(game.state.players_turn())
+                    && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) =>
+                {
+                    game.state.agree_draw()
+                }
+
+                Event::MouseButtonDown { x, y, .. } if x >= BOARD_LENGTH * TILE_SIZE => {
+                    if let Some(plies) = renderer.move_at(x, y) {
+                        match replay {
+                            Some((_, ref mut index)) => *index = plies,
+                            None => viewing = view(&game, plies),
+                        }
+                    }
+                }
+
+                Event::MouseButtonDown { x, y, .. } if !game_continue && replay.is_none() => {
+                    choice = renderer.choice_at(x, y);
+                }
+
+                Event::MouseMotion { x, y, .. } if game_continue && viewing.is_none() => {
+                    game.mouse_move(x, y);
+                }
+
+                Event::MouseButtonDown { x, y, .. }
+                    if game_continue
+                        && viewing.is_none()
+                        && computer != Some(game.state.players_turn()) =>
+                {
+                    game.mouse_down(x, y);
+                }
+
+                Event::MouseButtonUp { x, y, .. }
+                    if game_continue
+                        && viewing.is_none()
+                        && computer != Some(game.state.players_turn()) =>
+                {
+                    game.mouse_up(x, y);
                 }
                 _ => {}
             }
         }
-        renderer.draw(&gamestate)?;
-        match gamestate.end_game() {
+        match choice {
+            Some(GameOverChoice::Review) => {
+                let plies = game.state.history().len();
+                let positions = (0..plies)
+                    .map(|ply| Game::new(game.state.position_after(ply)))
+                    .chain(std::iter::once(game.clone()))
+                    .collect();
+                replay = Some((positions, plies));
+                viewing = None;
+            }
+            Some(choice) => {
+                if let Some(handle) = thinking.take() {
+                    stop_search.store(true, Ordering::Relaxed);
+                    let (returned, _) = handle.join().map_err(|_| "engine thread panicked")?;
+                    stop_search.store(false, Ordering::Relaxed);
+                    opponent = Some(returned);
+                }
+                if let Some(ref mut opponent) = opponent {
+                    opponent.new_game();
+                }
+                if choice == GameOverChoice::Rematch {
+                    computer = computer.map(Color::opposite);
+                }
+                let mut state = game.state.clone();
+                state.intialise_new_board();
+                game = Game::new(state);
+                replay = None;
+                viewing = None;
+                game_continue = true;
+            }
+            None => {}
+        }
+        game.state.check_clock(Instant::now());
+
+        let (shown, full_game, shown_plies) = match replay {
+            Some((ref positions, index)) => (
+                &positions[index],
+                &positions[positions.len() - 1].state,
+                index,
+            ),
+            None => match viewing {
+                Some((plies, ref position)) => (position, &game.state, plies),
+                None => (&game, &game.state, game.state.history().len()),
+            },
+        };
+        let book_moves: Vec<Move> = match book {
+            Some(ref book) if show_book => book
+                .moves(&shown.state)
+                .into_iter()
+                .map(|book_move| book_move.played)
+                .collect(),
+            _ => Vec::new(),
+        };
+        let game_over = (replay.is_none() && !game_continue).then(|| game.state.end_game());
+        if !show_analysis {
+            analysis = None;
+        } else if analysis
+            .as_ref()
+            .is_none_or(|(hash, _)| *hash != shown.state.hash())
+        {
+            analysis = Some((
+                shown.state.hash(),
+                analysis_lines(&shown.state, tablebase.as_ref()),
+            ));
+        }
+        let analysis_lines = analysis.as_ref().map_or(&[][..], |(_, lines)| lines);
+        renderer.draw(
+            shown,
+            full_game,
+            shown_plies,
+            &book_moves,
+            analysis_lines,
+            game_over,
+        )?;
+        match game.state.end_game() {
             PlayStatus::Continue => {}
-            PlayStatus::Draw => {
+            PlayStatus::Draw(reason) => {
                 if game_continue {
                     game_continue = false;
-                    println!("Draw!");
+                    println!("Draw by {reason}!");
                 }
             }
-            PlayStatus::Win(color) => {
+            PlayStatus::Win(color, reason) => {
                 if game_continue {
                     game_continue = false;
-                    let winner = if color == Color::Black {
-                        "Black"
-                    } else {
-                        "White"
-                    };
-                    println!("{winner} Won!");
+                    println!("{color} won by {reason}!");
                 }
             }
         }
+
+        if let Some(handle) = thinking.take_if(|handle| handle.is_finished()) {
+            let (returned, best) = handle.join().map_err(|_| "engine thread panicked")?;
+            opponent = Some(returned);
+            match best {
+                Some(best) if game_continue => game.play(best),
+                Some(_) => {}
+                None => {
+                    println!("The computer could not move; you now play both sides");
+                    computer = None;
+                }
+            }
+        } else if game_continue && thinking.is_none() && computer == Some(game.state.players_turn())
+        {
+            let random = RandomState::new().build_hasher().finish();
+            if let Some(book_move) = book
+                .as_ref()
+                .and_then(|book| book.choose(&game.state, random))
+            {
+                game.play(book_move);
+            } else if let Some(mut searcher) = opponent.take() {
+                let state = game.state.clone();
+                let movetime = match state.clock() {
+                    Some(clock) => {
+                        movetime.min(clock.remaining(state.players_turn(), Instant::now()) / 20)
+                    }
+                    None => movetime,
+                };
+                thinking = Some(thread::spawn(move || {
+                    let best = searcher.best_move(&state, movetime);
+                    (searcher, best)
+                }));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the live game as it stood after `plies` moves, or `None` once
+/// that is the current position.
+fn view(game: &Game, plies: usize) -> Option<(usize, Game)> {
+    (plies < game.state.history().len())
+        .then(|| (plies, Game::new(game.state.position_after(plies))))
+}
+
+/// Lines for the analysis panel: the tablebase result when a loaded table
+/// covers the position, otherwise the evaluation broken down by term.
+fn analysis_lines(gamestate: &GameState, tablebase: Option<&Tablebase>) -> Vec<String> {
+    if let Some(score) = tablebase.and_then(|tablebase| tablebase.probe(gamestate)) {
+        return vec![score.to_string()];
     }
+    let pawns = |score: i32| format!("{:+.2}", score as f64 / 100.0);
+    let evaluation = evaluate_terms(gamestate);
+    let mut lines = vec![format!("Evaluation {}", pawns(evaluation.total()))];
+    lines.extend(
+        evaluation
+            .terms()
+            .into_iter()
+            .map(|(name, score)| format!("    {name} {}", pawns(score))),
+    );
+    lines
+}
 
+fn perft(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let usage = "usage: chess perft <fen> <depth>";
+    let fen = args.next().ok_or(usage)?;
+    let depth = args
+        .next()
+        .ok_or(usage)?
+        .parse::<u32>()
+        .map_err(|e| format!("invalid depth: {e}"))?;
+    let mut gamestate = GameState::from_fen(&fen).map_err(|e| e.to_string())?;
+
+    let mut nodes = 0;
+    for (legal_move, count) in gamestate.divide(depth) {
+        println!("{legal_move}: {count}");
+        nodes += count;
+    }
+    println!();
+    println!("Nodes searched: {nodes}");
     Ok(())
 }
@@ -0,0 +1,42 @@
+use chess_core::engine::{Engine, SearchLimits};
+use chess_core::uci::UciEngine;
+use chess_core::util::*;
+use chess_core::GameState;
+use std::time::Duration;
+
+pub enum Opponent {
+    BuiltIn(Box<Engine>),
+    External(UciEngine),
+}
+
+impl Opponent {
+    pub fn new_game(&mut self) {
+        match self {
+            Opponent::BuiltIn(engine) => engine.clear(),
+            Opponent::External(engine) => {
+                if let Err(e) = engine.new_game() {
+                    println!("{}: {e}", engine.name().unwrap_or("engine"));
+                }
+            }
+        }
+    }
+
+    pub fn best_move(&mut self, gamestate: &GameState, movetime: Duration) -> Option<Move> {
+        match self {
+            Opponent::BuiltIn(engine) => {
+                let limits = SearchLimits {
+                    movetime: Some(movetime),
+                    ..SearchLimits::default()
+                };
+                engine.search(gamestate, limits, |_| {})
+            }
+            Opponent::External(engine) => match engine.best_move(gamestate, movetime) {
+                Ok(best) => Some(best),
+                Err(e) => {
+                    println!("{}: {e}", engine.name().unwrap_or("engine"));
+                    None
+                }
+            },
+        }
+    }
+}
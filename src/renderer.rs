@@ -1,50 +1,146 @@
 use super::images::Images;
-use crate::gamestate::GameState;
-use crate::util::*;
-use crate::{BOARD_LENGTH, TILE_SIZE};
+use crate::game::{promotion_picker_tiles, Game};
+use crate::{PANEL_WIDTH, TILE_SIZE};
+use chess_core::clock::Clock;
+use chess_core::util::{Color as PieceColor, *};
+use chess_core::{GameState, BOARD_LENGTH};
 use sdl2::gfx::primitives::DrawRenderer;
 use sdl2::pixels::Color;
 use sdl2::rect;
 use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator, TextureQuery};
+use sdl2::ttf::{Font, Sdl2TtfContext};
+use sdl2::video::{Window, WindowContext};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 const TRANSPARENCY: f64 = 0.6;
+const FONT_PATH: &str = "fonts/DejaVuSans.ttf";
+const FONT_SIZE: u16 = 16;
+const LARGE_FONT_SIZE: u16 = 40;
+const PANEL_MARGIN: i32 = 16;
+const CLOCK_HEIGHT: i32 = 64;
+const ROW_HEIGHT: i32 = 26;
+const ICON_SIZE: i32 = 22;
+const NUMBER_WIDTH: i32 = 44;
+const SAN_WIDTH: i32 = 100;
+const LOW_TIME: Duration = Duration::from_secs(10);
+const PANEL_COLOR: Color = Color::RGB(48, 46, 43);
+const TEXT_COLOR: Color = Color::RGB(220, 220, 220);
+const DIM_TEXT_COLOR: Color = Color::RGB(140, 138, 134);
+const HIGHLIGHT_COLOR: Color = Color::RGB(86, 83, 78);
+const DIALOG_WIDTH: i32 = 400;
+const DIALOG_HEIGHT: i32 = 300;
+const BUTTON_WIDTH: i32 = 300;
+const BUTTON_HEIGHT: i32 = 40;
+const BUTTON_COLOR: Color = Color::RGB(86, 83, 78);
+const STARTING_PIECES: [(PieceState, usize); 4] = [
+    (PieceState::Queen, 1),
+    (PieceState::Rook, 2),
+    (PieceState::Bishop, 2),
+    (PieceState::Knight, 2),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameOverChoice {
+    NewGame,
+    Rematch,
+    Review,
+}
+
+type TextKey = (String, (u8, u8, u8, u8));
 
 pub struct Renderer<'a> {
     canvas: Canvas<Window>,
     images: Images<'a>,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    font: Font<'a, 'static>,
+    large_font: Font<'a, 'static>,
+    text_cache: HashMap<TextKey, Texture<'a>>,
+    move_areas: Vec<(Rect, usize)>,
+    button_areas: Vec<(Rect, GameOverChoice)>,
 }
 
 impl<'a> Renderer<'a> {
-    pub fn new(canvas: Canvas<Window>, images: Images<'a>) -> Result<Self, String> {
-        Ok(Self { canvas, images })
+    pub fn new(
+        canvas: Canvas<Window>,
+        images: Images<'a>,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        ttf_context: &'a Sdl2TtfContext,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            canvas,
+            images,
+            texture_creator,
+            font: ttf_context.load_font(FONT_PATH, FONT_SIZE)?,
+            large_font: ttf_context.load_font(FONT_PATH, LARGE_FONT_SIZE)?,
+            text_cache: HashMap::new(),
+            move_areas: Vec::new(),
+            button_areas: Vec::new(),
+        })
     }
 
-    pub fn draw(&mut self, gamestate: &GameState) -> Result<(), String> {
-        self.canvas.copy(self.images.get_background(), None, None)?;
+    /// Returns the number of plies after the move drawn at `(x, y)` in the
+    /// move list, if any.
+    pub fn move_at(&self, x: i32, y: i32) -> Option<usize> {
+        self.move_areas
+            .iter()
+            .find(|(area, _)| area.contains_point((x, y)))
+            .map(|(_, plies)| *plies)
+    }
 
-        self.draw_selected_piece(gamestate.slected_piece_coord())?;
+    /// Returns the game-over dialog button drawn at `(x, y)`, if any.
+    pub fn choice_at(&self, x: i32, y: i32) -> Option<GameOverChoice> {
+        self.button_areas
+            .iter()
+            .find(|(area, _)| area.contains_point((x, y)))
+            .map(|(_, choice)| *choice)
+    }
+
+    pub fn draw(
+        &mut self,
+        game: &Game,
+        full_game: &GameState,
+        shown_plies: usize,
+        book_moves: &[Move],
+        analysis: &[String],
+        game_over: Option<PlayStatus>,
+    ) -> Result<(), String> {
+        let gamestate = &game.state;
+        let board_length = BOARD_LENGTH * TILE_SIZE;
+        self.canvas.copy(
+            self.images.get_background(),
+            None,
+            Rect::new(0, 0, board_length as u32, board_length as u32),
+        )?;
+        self.draw_panel(gamestate, full_game, shown_plies, analysis)?;
+
+        self.draw_selected_piece(game.slected_piece_coord())?;
 
         if gamestate.in_check() {
             let king_position = gamestate.get_king();
             self.draw_king_threatened(king_position)?;
         }
 
-        let mut moves = gamestate.legal_moves();
+        let mut moves = game.legal_moves();
 
-        if let Some(point) = gamestate.get_selected_tile() {
+        if let Some(point) = game.get_selected_tile() {
             if moves.contains(&point) {
                 moves.remove(&point);
                 self.draw_selected_tile(point)?;
             }
         }
 
-        if let Some(Move { src, dst }) = gamestate.last_move {
+        if let Some(Move { src, dst, .. }) = gamestate.last_move {
             self.draw_yellow_tile(src)?;
             self.draw_yellow_tile(dst)?;
         }
 
+        for Move { src, dst, .. } in book_moves {
+            self.draw_book_tile(*src)?;
+            self.draw_book_tile(*dst)?;
+        }
+
         for (y, x) in moves {
             if gamestate.is_empty((y, x)) {
                 self.draw_empty_move_tile((x, y))?;
@@ -54,11 +150,270 @@ impl<'a> Renderer<'a> {
         }
         self.draw_tiles(gamestate)?;
 
-        self.draw_moving_piece(gamestate.get_moving_piece())?;
+        self.draw_moving_piece(game.get_moving_piece())?;
+
+        if let Some((Move { dst, .. }, color)) = game.pending_promotion() {
+            self.draw_promotion_picker(dst, color)?;
+        }
+
+        self.button_areas.clear();
+        if let Some(status) = game_over {
+            self.draw_game_over(status)?;
+        }
         self.canvas.present();
         Ok(())
     }
 
+    fn draw_panel(
+        &mut self,
+        shown: &GameState,
+        full_game: &GameState,
+        shown_plies: usize,
+        analysis: &[String],
+    ) -> Result<(), String> {
+        let board_length = BOARD_LENGTH * TILE_SIZE;
+        self.canvas.set_draw_color(PANEL_COLOR);
+        self.canvas.fill_rect(Rect::new(
+            board_length,
+            0,
+            PANEL_WIDTH as u32,
+            board_length as u32,
+        ))?;
+
+        let mut top = PANEL_MARGIN;
+        let mut bottom = board_length - PANEL_MARGIN;
+        if let Some(clock) = full_game.clock() {
+            self.draw_clock(clock, PieceColor::Black, top)?;
+            self.draw_clock(clock, PieceColor::White, bottom - CLOCK_HEIGHT)?;
+            top += CLOCK_HEIGHT + PANEL_MARGIN / 2;
+            bottom -= CLOCK_HEIGHT + PANEL_MARGIN / 2;
+        }
+
+        self.draw_captures(shown, PieceColor::Black, top)?;
+        self.draw_captures(shown, PieceColor::White, bottom - ROW_HEIGHT)?;
+        top += ROW_HEIGHT + PANEL_MARGIN / 2;
+        bottom -= ROW_HEIGHT + PANEL_MARGIN / 2;
+
+        let left = board_length + PANEL_MARGIN;
+        for line in status(full_game).iter().rev() {
+            bottom -= ROW_HEIGHT;
+            self.draw_text(line, (left, bottom), TEXT_COLOR)?;
+        }
+        bottom -= PANEL_MARGIN / 2;
+
+        if !analysis.is_empty() {
+            for line in analysis.iter().rev() {
+                bottom -= ROW_HEIGHT;
+                self.draw_text(line, (left, bottom), DIM_TEXT_COLOR)?;
+            }
+            bottom -= PANEL_MARGIN / 2;
+        }
+
+        self.draw_move_list(full_game, shown_plies, top, bottom)
+    }
+
+    fn draw_clock(&mut self, clock: &Clock, color: PieceColor, y: i32) -> Result<(), String> {
+        let now = Instant::now();
+        let remaining = clock.remaining(color, now);
+        let running = clock.running() == Some(color);
+        let (background, foreground) = if running {
+            (Color::RGB(240, 240, 240), Color::RGB(30, 30, 30))
+        } else {
+            (Color::RGB(70, 68, 64), Color::RGB(200, 200, 200))
+        };
+        let foreground = if clock.flagged(now) == Some(color) || (running && remaining < LOW_TIME) {
+            Color::RGB(200, 40, 40)
+        } else {
+            foreground
+        };
+
+        let x = BOARD_LENGTH * TILE_SIZE + PANEL_MARGIN;
+        let width = PANEL_WIDTH - 2 * PANEL_MARGIN;
+        self.canvas.set_draw_color(background);
+        self.canvas
+            .fill_rect(Rect::new(x, y, width as u32, CLOCK_HEIGHT as u32))?;
+
+        self.draw_large_text(
+            &format_time(remaining),
+            Rect::new(x, y, width as u32, CLOCK_HEIGHT as u32),
+            foreground,
+        )
+    }
+
+    fn draw_game_over(&mut self, status: PlayStatus) -> Result<(), String> {
+        let (title, reason) = match status {
+            PlayStatus::Continue => return Ok(()),
+            PlayStatus::Win(color, reason) => (format!("{color} wins"), reason),
+            PlayStatus::Draw(reason) => ("Draw".to_string(), reason),
+        };
+
+        let board_length = BOARD_LENGTH * TILE_SIZE;
+        let blend_mode = self.canvas.blend_mode();
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 120));
+        self.canvas
+            .fill_rect(Rect::new(0, 0, board_length as u32, board_length as u32))?;
+        self.canvas.set_blend_mode(blend_mode);
+
+        let left = (board_length - DIALOG_WIDTH) / 2;
+        let top = (board_length - DIALOG_HEIGHT) / 2;
+        self.canvas.set_draw_color(PANEL_COLOR);
+        self.canvas.fill_rect(Rect::new(
+            left,
+            top,
+            DIALOG_WIDTH as u32,
+            DIALOG_HEIGHT as u32,
+        ))?;
+
+        let mut y = top + PANEL_MARGIN;
+        self.draw_large_text(
+            &title,
+            Rect::new(left, y, DIALOG_WIDTH as u32, 56),
+            TEXT_COLOR,
+        )?;
+        y += 56;
+        self.draw_centered_text(
+            &format!("by {reason}"),
+            Rect::new(left, y, DIALOG_WIDTH as u32, ROW_HEIGHT as u32),
+            DIM_TEXT_COLOR,
+        )?;
+        y += ROW_HEIGHT + PANEL_MARGIN;
+
+        for (label, choice) in [
+            ("New game (N)", GameOverChoice::NewGame),
+            ("Rematch, colours swapped (C)", GameOverChoice::Rematch),
+            ("Review game (R)", GameOverChoice::Review),
+        ] {
+            let area = Rect::new(
+                left + (DIALOG_WIDTH - BUTTON_WIDTH) / 2,
+                y,
+                BUTTON_WIDTH as u32,
+                BUTTON_HEIGHT as u32,
+            );
+            self.canvas.set_draw_color(BUTTON_COLOR);
+            self.canvas.fill_rect(area)?;
+            self.draw_centered_text(label, area, TEXT_COLOR)?;
+            self.button_areas.push((area, choice));
+            y += BUTTON_HEIGHT + PANEL_MARGIN / 2;
+        }
+        Ok(())
+    }
+
+    fn draw_captures(
+        &mut self,
+        gamestate: &GameState,
+        color: PieceColor,
+        y: i32,
+    ) -> Result<(), String> {
+        let opponent = color.opposite();
+        let mut x = BOARD_LENGTH * TILE_SIZE + PANEL_MARGIN;
+        for state in missing_pieces(gamestate, opponent) {
+            let piece = Piece {
+                state,
+                color: opponent,
+                has_moved: true,
+            };
+            self.canvas.copy(
+                self.images.get(piece),
+                None,
+                Rect::new(
+                    x,
+                    y + (ROW_HEIGHT - ICON_SIZE) / 2,
+                    ICON_SIZE as u32,
+                    ICON_SIZE as u32,
+                ),
+            )?;
+            x += ICON_SIZE * 2 / 3;
+        }
+
+        let balance = material(gamestate, color) - material(gamestate, opponent);
+        if balance > 0 {
+            self.draw_text(&format!("+{balance}"), (x + ICON_SIZE / 2, y), TEXT_COLOR)?;
+        }
+        Ok(())
+    }
+
+    fn draw_move_list(
+        &mut self,
+        gamestate: &GameState,
+        shown_plies: usize,
+        top: i32,
+        bottom: i32,
+    ) -> Result<(), String> {
+        self.move_areas.clear();
+        let (first_number, first_color) = gamestate.first_move();
+        let offset = (first_color == PieceColor::Black) as usize;
+        let history = gamestate.history();
+        let rows = (history.len() + offset).div_ceil(2);
+        let capacity = ((bottom - top) / ROW_HEIGHT).max(1) as usize;
+        let focus = (shown_plies.max(1) - 1 + offset) / 2;
+        let first_row = focus.saturating_sub(capacity - 1);
+
+        let left = BOARD_LENGTH * TILE_SIZE + PANEL_MARGIN;
+        for row in first_row..rows.min(first_row + capacity) {
+            let y = top + (row - first_row) as i32 * ROW_HEIGHT;
+            self.draw_text(
+                &format!("{}.", first_number + row as i32),
+                (left, y + 3),
+                DIM_TEXT_COLOR,
+            )?;
+            for column in 0..2 {
+                let Some(ply) = (row * 2 + column).checked_sub(offset) else {
+                    continue;
+                };
+                let Some(played) = history.get(ply) else {
+                    continue;
+                };
+                let x = left + NUMBER_WIDTH + column as i32 * SAN_WIDTH;
+                let area = Rect::new(x - 4, y, (SAN_WIDTH - 4) as u32, ROW_HEIGHT as u32);
+                if ply + 1 == shown_plies {
+                    self.canvas.set_draw_color(HIGHLIGHT_COLOR);
+                    self.canvas.fill_rect(area)?;
+                }
+                self.draw_text(&played.san, (x, y + 3), TEXT_COLOR)?;
+                self.move_areas.push((area, ply + 1));
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_text(&mut self, text: &str, (x, y): (i32, i32), color: Color) -> Result<(), String> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        let key = (text.to_string(), color.rgba());
+        if !self.text_cache.contains_key(&key) {
+            let texture = render_text(self.texture_creator, &self.font, text, color)?;
+            self.text_cache.insert(key.clone(), texture);
+        }
+        let texture = &self.text_cache[&key];
+        let TextureQuery { width, height, .. } = texture.query();
+        self.canvas
+            .copy(texture, None, Rect::new(x, y, width, height))
+    }
+
+    fn draw_centered_text(&mut self, text: &str, area: Rect, color: Color) -> Result<(), String> {
+        let (width, height) = self.font.size_of(text).map_err(|e| e.to_string())?;
+        let x = area.x() + (area.width() as i32 - width as i32) / 2;
+        let y = area.y() + (area.height() as i32 - height as i32) / 2;
+        self.draw_text(text, (x, y), color)
+    }
+
+    fn draw_large_text(&mut self, text: &str, area: Rect, color: Color) -> Result<(), String> {
+        let texture = render_text(self.texture_creator, &self.large_font, text, color)?;
+        let TextureQuery { width, height, .. } = texture.query();
+        self.canvas.copy(
+            &texture,
+            None,
+            Rect::new(
+                area.x() + (area.width() as i32 - width as i32) / 2,
+                area.y() + (area.height() as i32 - height as i32) / 2,
+                width,
+                height,
+            ),
+        )
+    }
+
     fn draw_tiles(&mut self, gamestate: &GameState) -> Result<(), String> {
         for i in 0..BOARD_LENGTH {
             for j in 0..BOARD_LENGTH {
@@ -86,6 +441,14 @@ impl<'a> Renderer<'a> {
         Ok(())
     }
 
+    fn draw_book_tile(&mut self, (y, x): Point) -> Result<(), String> {
+        let alpha = TRANSPARENCY * 255.0;
+        let alpha = alpha as u8;
+        let color = Color::RGBA(86, 130, 170, alpha);
+        self.draw_square((x, y), color)?;
+        Ok(())
+    }
+
     fn draw_selected_tile(&mut self, (y, x): Point) -> Result<(), String> {
         let alpha = TRANSPARENCY * 255.0;
         let alpha = alpha as u8;
@@ -192,6 +555,21 @@ impl<'a> Renderer<'a> {
         Ok(())
     }
 
+    fn draw_promotion_picker(&mut self, dst: Point, color: PieceColor) -> Result<(), String> {
+        for ((y, x), state) in promotion_picker_tiles(dst, color) {
+            self.draw_square((x, y), Color::RGB(240, 240, 240))?;
+            self.draw_tile(
+                (x, y),
+                Tile::Piece(Piece {
+                    state,
+                    color,
+                    has_moved: true,
+                }),
+            )?;
+        }
+        Ok(())
+    }
+
     fn draw_moving_piece(&mut self, piece: Option<(Piece, Point)>) -> Result<(), String> {
         if let Some((piece, (x, y))) = piece {
             let half_tile_size = TILE_SIZE / 2;
@@ -209,3 +587,75 @@ impl<'a> Renderer<'a> {
         Ok(())
     }
 }
+
+fn format_time(remaining: Duration) -> String {
+    let seconds = remaining.as_secs_f64().ceil() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+fn render_text<'a>(
+    texture_creator: &'a TextureCreator<WindowContext>,
+    font: &Font,
+    text: &str,
+    color: Color,
+) -> Result<Texture<'a>, String> {
+    let surface = font
+        .render(text)
+        .blended(color)
+        .map_err(|e| e.to_string())?;
+    texture_creator
+        .create_texture_from_surface(&surface)
+        .map_err(|e| e.to_string())
+}
+
+fn status(gamestate: &GameState) -> Vec<String> {
+    match gamestate.end_game() {
+        PlayStatus::Continue => vec![format!("{} to move", gamestate.players_turn())],
+        PlayStatus::Win(color, reason) => {
+            let result = if color == PieceColor::White {
+                "1-0"
+            } else {
+                "0-1"
+            };
+            vec![result.to_string(), format!("{color} won by {reason}")]
+        }
+        PlayStatus::Draw(reason) => vec!["½-½".to_string(), format!("Draw by {reason}")],
+    }
+}
+
+fn count(gamestate: &GameState, color: PieceColor, state: PieceState) -> usize {
+    gamestate
+        .pieces(color)
+        .iter()
+        .filter(|point| gamestate.get_piece(**point).map(|piece| piece.state) == Some(state))
+        .count()
+}
+
+fn missing_pieces(gamestate: &GameState, color: PieceColor) -> Vec<PieceState> {
+    let mut missing = Vec::new();
+    let mut promoted = 0;
+    for (state, starting) in STARTING_PIECES {
+        let on_board = count(gamestate, color, state);
+        promoted += on_board.saturating_sub(starting);
+        missing.extend(std::iter::repeat_n(
+            state,
+            starting.saturating_sub(on_board),
+        ));
+    }
+    let pawns = 8usize.saturating_sub(count(gamestate, color, PieceState::Pawn) + promoted);
+    missing.extend(std::iter::repeat_n(PieceState::Pawn, pawns));
+    missing
+}
+
+fn material(gamestate: &GameState, color: PieceColor) -> i32 {
+    use PieceState::*;
+    [(Queen, 9), (Rook, 5), (Bishop, 3), (Knight, 3), (Pawn, 1)]
+        .into_iter()
+        .map(|(state, value)| count(gamestate, color, state) as i32 * value)
+        .sum()
+}